@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
-use csq::CSQReader;
-use image::{Rgb, RgbImage};
+use csq::{CSQReader, Palette, Renderer};
+use image::RgbImage;
 use ndarray::Array2;
 use opencv::{
     core::{Mat, CV_8UC3},
@@ -9,6 +9,7 @@ use opencv::{
     videoio::{VideoWriter, VideoWriterTrait},
 };
 use std::{
+    collections::HashMap,
     path::PathBuf,
     sync::{mpsc, Arc, Mutex},
     thread,
@@ -22,49 +23,53 @@ struct Cli {
     input_file: PathBuf,
     #[clap(short = 'o', long = "output-dir")]
     output_dir: Option<PathBuf>,
+    /// Number of threads used to decode frames, 0 uses one per CPU.
+    #[clap(short = 't', long = "threads", default_value_t = 0)]
+    threads: usize,
 }
 
-pub fn create_image_from_frame(frame: &Frame) -> Result<RgbImage> {
-    let (min_temp, max_temp) = frame.iter().fold(
-        (f32::INFINITY, f32::NEG_INFINITY),
-        |(min_temp, max_temp), value| (min_temp.min(*value), max_temp.max(*value)),
-    );
+pub fn create_image_from_frame(renderer: &Renderer, frame: &Frame) -> Result<RgbImage> {
+    let rgb = renderer.render(frame);
+    let (height, width, _) = rgb.dim();
 
-    let normalized_values = frame.mapv(|v| {
-        let min = min_temp;
-        let max = max_temp;
-        (v - min) / (max - min)
-    });
-
-    let width = normalized_values.shape()[1] as u32;
-    let height = normalized_values.shape()[0] as u32;
-
-    let mut img = RgbImage::new(width, height);
-
-    let gradient = colorgrad::rainbow();
-
-    for (x, column) in normalized_values.columns().into_iter().enumerate() {
-        for (y, &value) in column.iter().enumerate() {
-            let color = gradient.at(value.into());
-            img.put_pixel(
-                x as u32,
-                y as u32,
-                Rgb([
-                    (color.r * 255.0) as u8,
-                    (color.g * 255.0) as u8,
-                    (color.b * 255.0) as u8,
-                ]),
-            );
-        }
-    }
-
-    Ok(img)
+    RgbImage::from_raw(width as u32, height as u32, rgb.into_raw_vec())
+        .ok_or_else(|| anyhow::anyhow!("Rendered image has the wrong size"))
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
-    let mut reader = CSQReader::new(&args.input_file);
+    let mut reader = CSQReader::new(&args.input_file)?;
+
+    // Write the video at the frame rate of the camera, and repeat the frame before a gap for
+    // every dropped frame, so the video keeps the timing of the recording.
+    let report = reader.frame_rate_report()?;
+    let fps = report.nominal.or(report.effective).unwrap_or(30.0);
+    if !report.gaps.is_empty() {
+        eprintln!(
+            "{} frames were dropped, the effective frame rate is {:.2} fps",
+            report.dropped(),
+            report.effective.unwrap_or(fps)
+        );
+    }
+    let gaps: HashMap<usize, usize> = report
+        .gaps
+        .iter()
+        .map(|gap| (gap.after, gap.dropped))
+        .collect();
+
+    // Draw the frames with the palette the camera displayed them with, taken from the first
+    // frame that can be read, as the first frames of a recording may be damaged.
+    let (first, metadata) = (0..reader.frame_count()?)
+        .find_map(|index| reader.read_metadata(index).ok().map(|m| (index, m)))
+        .ok_or_else(|| anyhow::anyhow!("No frame of the file could be read"))?;
+    let palette = reader
+        .read_palette(first)?
+        .unwrap_or_else(Palette::grayscale);
+    let mut renderer = Renderer::new(palette);
+    if let Some((min, max)) = metadata.camera_temperature_range() {
+        renderer = renderer.camera_range(min, max);
+    }
 
     let (frames_tx, frames_rx) = mpsc::channel::<Frame>();
 
@@ -72,7 +77,7 @@ fn main() -> Result<()> {
         VideoWriter::new(
             "test.mp4",
             VideoWriter::fourcc('a', 'v', 'c', '1').expect("Failed to create fourcc"),
-            30.0,
+            fps,
             (1024, 768).into(),
             true,
         )
@@ -82,7 +87,7 @@ fn main() -> Result<()> {
     let video_writer_clone = video_writer.clone();
     let processing_thread = thread::spawn(move || {
         while let Ok(data) = frames_rx.recv() {
-            let img = create_image_from_frame(&data).unwrap();
+            let img = create_image_from_frame(&renderer, &data).unwrap();
 
             let (width, height) = (&img.width(), &img.height());
             let raw = img.into_raw();
@@ -98,10 +103,13 @@ fn main() -> Result<()> {
         }
     });
 
-    for (i, frame) in reader.frames().enumerate() {
+    for (i, frame) in reader.parallel_frames(args.threads)?.enumerate() {
         match frame {
             Ok(frame) => {
-                frames_tx.send(*frame).unwrap();
+                for _ in 0..gaps.get(&frame.index).copied().unwrap_or(0) {
+                    frames_tx.send(frame.temperatures.clone()).unwrap();
+                }
+                frames_tx.send(frame.temperatures).unwrap();
                 println!("Frame: {}", i + 1);
             }
             Err(e) => {
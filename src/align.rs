@@ -0,0 +1,313 @@
+use ndarray::{Array2, Array3};
+
+use crate::types::CSQExifData;
+
+/// Radius of the box blur that separates the edges of the visual image for MSX.
+const MSX_BLUR_RADIUS: isize = 2;
+
+/// Where the thermal image lies within the visual image, from the PiP record of the camera.
+///
+/// The visual image is scaled to be `real2ir` times as wide as the thermal image. In the scaled
+/// visual image, the center of the thermal image is `offset_x` and `offset_y` pixels from its
+/// center. The picture-in-picture window is the part of the thermal image from (`pip_x1`,
+/// `pip_y1`) to (`pip_x2`, `pip_y2`), in thermal pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alignment {
+    pub real2ir: f32,
+    pub offset_x: i16,
+    pub offset_y: i16,
+    pub pip_x1: i16,
+    pub pip_x2: i16,
+    pub pip_y1: i16,
+    pub pip_y2: i16,
+}
+
+impl Alignment {
+    /// Reads the alignment from the PiP fields of `metadata`. Returns `None` for cameras without
+    /// a visual camera.
+    pub fn from_metadata(metadata: &CSQExifData) -> Option<Self> {
+        let real2ir = metadata.real2ir?;
+        if !real2ir.is_finite() || real2ir <= 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            real2ir,
+            offset_x: metadata.offset_x?,
+            offset_y: metadata.offset_y?,
+            pip_x1: metadata.pip_x1?,
+            pip_x2: metadata.pip_x2?,
+            pip_y1: metadata.pip_y1?,
+            pip_y2: metadata.pip_y2?,
+        })
+    }
+
+    /// Maps a position in the thermal image to the visual image. Sizes are (height, width).
+    pub fn thermal_to_visual(
+        &self,
+        (x, y): (f32, f32),
+        thermal_size: (usize, usize),
+        visual_size: (usize, usize),
+    ) -> (f32, f32) {
+        let m = Mapping::new(self, thermal_size, visual_size);
+        ((x + m.origin_x) * m.scale, (y + m.origin_y) * m.scale)
+    }
+
+    /// Maps a position in the visual image to the thermal image. Sizes are (height, width).
+    pub fn visual_to_thermal(
+        &self,
+        (x, y): (f32, f32),
+        thermal_size: (usize, usize),
+        visual_size: (usize, usize),
+    ) -> (f32, f32) {
+        let m = Mapping::new(self, thermal_size, visual_size);
+        (x / m.scale - m.origin_x, y / m.scale - m.origin_y)
+    }
+
+    /// Resamples `thermal` onto the pixels of a visual image of `visual_size` (height, width).
+    /// Pixels outside of the thermal image are NaN.
+    pub fn warp_thermal_to_visual(
+        &self,
+        thermal: &Array2<f32>,
+        visual_size: (usize, usize),
+    ) -> Array2<f32> {
+        let thermal_size = thermal.dim();
+        Array2::from_shape_fn(visual_size, |(y, x)| {
+            let (tx, ty) = self.visual_to_thermal((x as f32, y as f32), thermal_size, visual_size);
+            bilinear(thermal_size, tx, ty, |y, x| thermal[[y, x]]).unwrap_or(f32::NAN)
+        })
+    }
+
+    /// Resamples the RGB `visual` image onto the pixels of a thermal image of `thermal_size`
+    /// (height, width). Pixels outside of the visual image are black.
+    pub fn warp_visual_to_thermal(
+        &self,
+        visual: &Array3<u8>,
+        thermal_size: (usize, usize),
+    ) -> Array3<u8> {
+        let (height, width, channels) = visual.dim();
+        let visual_size = (height, width);
+        Array3::from_shape_fn((thermal_size.0, thermal_size.1, channels), |(y, x, c)| {
+            let (vx, vy) = self.thermal_to_visual((x as f32, y as f32), thermal_size, visual_size);
+            bilinear(visual_size, vx, vy, |y, x| visual[[y, x, c]] as f32)
+                .map_or(0, |v| v.round() as u8)
+        })
+    }
+
+    /// Draws the picture-in-picture window of the RGB `thermal` image, e.g. as rendered with
+    /// a palette, over the RGB `visual` image, with the given `opacity` between 0 and 1. Only
+    /// the channels both images have are blended.
+    pub fn blend_pip(&self, visual: &Array3<u8>, thermal: &Array3<u8>, opacity: f32) -> Array3<u8> {
+        let (height, width, visual_channels) = visual.dim();
+        let (thermal_height, thermal_width, thermal_channels) = thermal.dim();
+        let channels = visual_channels.min(thermal_channels);
+        let thermal_size = (thermal_height, thermal_width);
+        let opacity = opacity.clamp(0.0, 1.0);
+
+        let (x1, x2) = (self.pip_x1.min(self.pip_x2), self.pip_x1.max(self.pip_x2));
+        let (y1, y2) = (self.pip_y1.min(self.pip_y2), self.pip_y1.max(self.pip_y2));
+
+        let mut blended = visual.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (tx, ty) =
+                    self.visual_to_thermal((x as f32, y as f32), thermal_size, (height, width));
+                if tx < x1 as f32 || tx > x2 as f32 || ty < y1 as f32 || ty > y2 as f32 {
+                    continue;
+                }
+                for c in 0..channels {
+                    let Some(t) = bilinear(thermal_size, tx, ty, |y, x| thermal[[y, x, c]] as f32)
+                    else {
+                        continue;
+                    };
+                    let v = visual[[y, x, c]] as f32;
+                    blended[[y, x, c]] = (v + (t - v) * opacity).round() as u8;
+                }
+            }
+        }
+        blended
+    }
+
+    /// Adds the edges of the RGB `visual` image to the RGB `thermal` image, like FLIR's MSX,
+    /// so the scene can be recognized in the thermal image. `strength` scales the edges, 1 is
+    /// a good start. A `visual` image with fewer than three channels is taken as grayscale.
+    pub fn msx(&self, thermal: &Array3<u8>, visual: &Array3<u8>, strength: f32) -> Array3<u8> {
+        let (height, width, _) = thermal.dim();
+        let visual = self.warp_visual_to_thermal(visual, (height, width));
+
+        let luminance = Array2::from_shape_fn((height, width), |(y, x)| match visual.dim().2 {
+            0 => 0.0,
+            1 | 2 => visual[[y, x, 0]] as f32,
+            _ => {
+                0.299 * visual[[y, x, 0]] as f32
+                    + 0.587 * visual[[y, x, 1]] as f32
+                    + 0.114 * visual[[y, x, 2]] as f32
+            }
+        });
+        let blurred = box_blur(&luminance, MSX_BLUR_RADIUS);
+
+        Array3::from_shape_fn(thermal.dim(), |(y, x, c)| {
+            let edge = luminance[[y, x]] - blurred[[y, x]];
+            (thermal[[y, x, c]] as f32 + edge * strength)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+/// The thermal image in the coordinates of the scaled visual image, and the scale to the pixels
+/// of the visual image.
+struct Mapping {
+    origin_x: f32,
+    origin_y: f32,
+    scale: f32,
+}
+
+impl Mapping {
+    fn new(
+        alignment: &Alignment,
+        thermal_size: (usize, usize),
+        visual_size: (usize, usize),
+    ) -> Self {
+        let (thermal_height, thermal_width) = (thermal_size.0 as f32, thermal_size.1 as f32);
+        let (visual_height, visual_width) = (visual_size.0 as f32, visual_size.1 as f32);
+
+        let scaled_width = thermal_width * alignment.real2ir;
+        let scale = visual_width / scaled_width;
+        let scaled_height = visual_height / scale;
+
+        Self {
+            origin_x: (scaled_width - thermal_width) / 2.0 + alignment.offset_x as f32,
+            origin_y: (scaled_height - thermal_height) / 2.0 + alignment.offset_y as f32,
+            scale,
+        }
+    }
+}
+
+/// Interpolates between the four pixels around (`x`, `y`) of an image of `size` (height, width).
+/// Returns `None` outside of the image and for empty images.
+fn bilinear(
+    (height, width): (usize, usize),
+    x: f32,
+    y: f32,
+    pixel: impl Fn(usize, usize) -> f32,
+) -> Option<f32> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    if !(x >= 0.0 && y >= 0.0 && x <= (width - 1) as f32 && y <= (height - 1) as f32) {
+        return None;
+    }
+
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let top = pixel(y0, x0) * (1.0 - fx) + pixel(y0, x1) * fx;
+    let bottom = pixel(y1, x0) * (1.0 - fx) + pixel(y1, x1) * fx;
+    Some(top * (1.0 - fy) + bottom * fy)
+}
+
+fn box_blur(image: &Array2<f32>, radius: isize) -> Array2<f32> {
+    let (height, width) = image.dim();
+    Array2::from_shape_fn((height, width), |(y, x)| {
+        let mut sum = 0.0;
+        let mut count = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (sy, sx) = (y as isize + dy, x as isize + dx);
+                if sy >= 0 && sx >= 0 && (sy as usize) < height && (sx as usize) < width {
+                    sum += image[[sy as usize, sx as usize]];
+                    count += 1;
+                }
+            }
+        }
+        sum / count as f32
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alignment() -> Alignment {
+        Alignment {
+            real2ir: 1.5,
+            offset_x: 0,
+            offset_y: 0,
+            pip_x1: 0,
+            pip_x2: 3,
+            pip_y1: 0,
+            pip_y2: 3,
+        }
+    }
+
+    #[test]
+    fn bilinear_of_empty_image_is_none() {
+        assert_eq!(bilinear((0, 0), 0.0, 0.0, |_, _| 1.0), None);
+        assert_eq!(bilinear((0, 4), 0.0, 0.0, |_, _| 1.0), None);
+        assert_eq!(bilinear((4, 0), 0.0, 0.0, |_, _| 1.0), None);
+    }
+
+    #[test]
+    fn warping_empty_images_does_not_panic() {
+        let alignment = alignment();
+
+        let warped = alignment.warp_thermal_to_visual(&Array2::zeros((0, 0)), (2, 3));
+        assert_eq!(warped.dim(), (2, 3));
+        assert!(warped.iter().all(|t| t.is_nan()));
+
+        let warped = alignment.warp_visual_to_thermal(&Array3::zeros((0, 0, 3)), (2, 2));
+        assert_eq!(warped.dim(), (2, 2, 3));
+        assert!(warped.iter().all(|&v| v == 0));
+
+        let visual = Array3::from_elem((2, 2, 3), 7);
+        let blended = alignment.blend_pip(&visual, &Array3::zeros((0, 0, 3)), 0.5);
+        assert_eq!(blended, visual);
+    }
+
+    #[test]
+    fn mapping_round_trips_with_scale_and_offset() {
+        let alignment = Alignment {
+            real2ir: 1.8,
+            offset_x: 5,
+            offset_y: -3,
+            ..alignment()
+        };
+        let (thermal_size, visual_size) = ((240, 320), (480, 640));
+
+        // The center of the thermal image is offset from the center of the visual image.
+        let scale = 640.0 / (320.0 * 1.8);
+        let (x, y) = alignment.thermal_to_visual((160.0, 120.0), thermal_size, visual_size);
+        assert!((x - (320.0 + 5.0 * scale)).abs() < 1e-3, "{}", x);
+        assert!((y - (240.0 - 3.0 * scale)).abs() < 1e-3, "{}", y);
+
+        for point in [(0.0, 0.0), (12.5, 200.0), (319.0, 239.0), (-4.0, 250.0)] {
+            let visual = alignment.thermal_to_visual(point, thermal_size, visual_size);
+            let (x, y) = alignment.visual_to_thermal(visual, thermal_size, visual_size);
+            assert!(
+                (x - point.0).abs() < 1e-3 && (y - point.1).abs() < 1e-3,
+                "{:?}",
+                point
+            );
+        }
+    }
+
+    #[test]
+    fn images_with_other_channel_counts_are_blended() {
+        let alignment = alignment();
+        let gray = Array3::from_elem((4, 4, 1), 100);
+        let rgba = Array3::from_elem((4, 4, 4), 200);
+
+        let blended = alignment.blend_pip(&gray, &rgba, 0.5);
+        assert_eq!(blended.dim(), (4, 4, 1));
+        assert!(blended.iter().any(|&v| v == 150));
+
+        let blended = alignment.blend_pip(&rgba, &gray, 1.0);
+        assert_eq!(blended.dim(), (4, 4, 4));
+        assert!(blended.iter().all(|&v| v == 200 || v == 100));
+
+        let msx = alignment.msx(&rgba, &gray, 1.0);
+        assert_eq!(msx.dim(), (4, 4, 4));
+    }
+}
@@ -1,160 +1,602 @@
-use anyhow::{anyhow, Result};
-use lazy_static::lazy_static;
-use ndarray::Array2;
-use pcre2::bytes::Regex;
-use peck_exif::exif::{exiftool_available, Exif, Mode};
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
-use std::path::Path;
-use std::process::Command;
-use std::str;
-use std::time::Instant;
-use tempfile::NamedTempFile;
-
-use crate::utils::raw_to_temp;
-use crate::{types::CSQExifData, utils::decode_jpeg_py};
-
-const BLOCKSIZE: usize = 1000000;
-
-lazy_static! {
-    static ref MAGIC_SEQUENCE: Regex =
-        Regex::new(str::from_utf8(b"\x46\x46\x46\x00\x52\x54").unwrap()).unwrap();
-}
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::fff::{self, FFFData};
+use crate::index::{FrameIndex, IndexEntry};
+use crate::measurement::Measurement;
+use crate::palette::Palette;
+use crate::parallel::ParallelFrames;
+use crate::splitter::{FileFormat, FrameSplitter};
+use crate::timeline::{FrameRateReport, Timeline};
+use crate::types::{
+    CSQExifData, DecodedFrame, Frame, MetadataOverrides, RawFrame, RecoveryPolicy, SkippedFrame,
+};
+use crate::visual::VisualImage;
 
 pub struct CSQReader {
+    path: PathBuf,
     reader: BufReader<File>,
-    leftover: Vec<u8>,
-    imgs: Vec<Vec<u8>>,
-    index: usize,
+    /// `None` until the file is long enough to detect its format.
+    format: Option<FileFormat>,
+    splitter: FrameSplitter,
+    next_index: usize,
+    frame_index: Option<Vec<IndexEntry>>,
+    recovery: RecoveryPolicy,
+    skipped: Vec<SkippedFrame>,
+    /// The shape and metadata of the last good frame, used for placeholders.
+    last_good: Option<((usize, usize), Arc<CSQExifData>)>,
+    /// The idle timeout when following a file that is still being written.
+    follow: Option<Duration>,
+    overrides: MetadataOverrides,
 }
 
 impl CSQReader {
-    pub fn new(filename: &Path) -> Self {
-        if !exiftool_available() {
-            panic!("Exiftool not available for execution.");
-        }
-
-        let file = File::open(filename).unwrap_or_else(|_| {
-            panic!("Failed to open file: {}", filename.display());
-        });
-        let reader = BufReader::new(file);
+    pub fn new(filename: &Path) -> Result<Self> {
+        let file = File::open(filename)?;
+        let mut reader = BufReader::new(file);
+        let format = FileFormat::detect(&mut reader)?;
 
-        Self {
+        Ok(Self {
+            path: filename.to_path_buf(),
             reader,
-            leftover: vec![],
-            imgs: vec![],
-            index: 0,
+            format,
+            splitter: FrameSplitter::new(0).format(format),
+            next_index: 0,
+            frame_index: None,
+            recovery: RecoveryPolicy::default(),
+            skipped: vec![],
+            last_good: None,
+            follow: None,
+            overrides: MetadataOverrides::default(),
+        })
+    }
+
+    /// Follows a file that is still being recorded. Instead of ending at the end of the file,
+    /// iterating over the frames waits for the file to grow, and only ends once the file did
+    /// not grow for `idle_timeout`. `None` turns following off again.
+    ///
+    /// The parallel iterators wait until a whole batch of frames was written before decoding
+    /// it, so `frames` returns new frames sooner.
+    pub fn set_follow(&mut self, idle_timeout: Option<Duration>) {
+        self.follow = idle_timeout;
+        self.splitter = std::mem::take(&mut self.splitter).follow(idle_timeout);
+    }
+
+    /// Sets the values used for calibration and object parameters that the frames lack, before
+    /// falling back to the FLIR defaults. The values that were assumed for a frame are listed in
+    /// `CSQExifData::assumed_values`.
+    pub fn set_metadata_overrides(&mut self, overrides: MetadataOverrides) {
+        self.overrides = overrides;
+    }
+
+    pub fn metadata_overrides(&self) -> &MetadataOverrides {
+        &self.overrides
+    }
+
+    fn splitter_at(&self, offset: u64) -> FrameSplitter {
+        FrameSplitter::new(offset)
+            .format(self.format)
+            .follow(self.follow)
+    }
+
+    /// Whether the file is a CSQ video or a legacy SEQ sequence. Returns `None` if the file was
+    /// too short to tell so far, which only happens for files that are still being recorded.
+    pub fn format(&self) -> Option<FileFormat> {
+        self.format
+    }
+
+    /// Sets what happens when a damaged frame is found while iterating over the file.
+    /// `read_frame` always returns the error of a damaged frame.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.recovery = policy;
+    }
+
+    pub fn recovery_policy(&self) -> RecoveryPolicy {
+        self.recovery
+    }
+
+    /// The damaged frames that were skipped or replaced by placeholders so far.
+    pub fn skipped_frames(&self) -> &[SkippedFrame] {
+        &self.skipped
+    }
+
+    /// Scans the file from `offset` on for the start and length of every frame, without
+    /// decoding any of them.
+    fn scan_frames(&mut self, offset: u64) -> Result<Vec<(u64, u64)>> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+
+        let mut splitter = FrameSplitter::new(offset).format(self.format);
+        let mut frames = vec![];
+        while let Some(frame) = splitter.skip_frame(&mut self.reader)? {
+            frames.push(frame);
         }
+        self.format = self.format.or(splitter.detected_format());
+
+        Ok(frames)
     }
 
-    fn populate_list(&mut self) -> Result<()> {
-        self.imgs.clear();
-        self.index = 0;
+    /// Runs `f`, and restores the position of the reader afterwards, also if `f` failed.
+    fn restoring_position<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let position = self.reader.stream_position()?;
+        let result = f(self);
+        let restored = self.reader.seek(SeekFrom::Start(position));
 
-        let mut buffer = [0; BLOCKSIZE];
-        let read_amount = self.reader.read(&mut buffer[..])?;
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
 
-        if read_amount == 0 {
-            return Ok(());
+    fn build_index(&mut self) -> Result<Vec<IndexEntry>> {
+        self.restoring_position(|this| this.scan_index(0))
+    }
+
+    /// Indexes the frames from `offset` on.
+    fn scan_index(&mut self, offset: u64) -> Result<Vec<IndexEntry>> {
+        let frames = self.scan_frames(offset)?;
+
+        let mut entries = Vec::with_capacity(frames.len());
+        for (offset, length) in frames {
+            // A damaged header only loses the timestamp, the frame itself is still indexed.
+            let timestamp = fff::read_capture_time(&mut self.reader, offset)
+                .ok()
+                .flatten();
+
+            entries.push(IndexEntry {
+                offset,
+                length,
+                timestamp,
+            });
         }
 
-        if buffer.is_empty() {
-            return Err(anyhow!("File is empty"));
+        Ok(entries)
+    }
+
+    fn frame_entries(&mut self) -> Result<&[IndexEntry]> {
+        if self.frame_index.is_none() {
+            self.frame_index = Some(self.build_index()?);
         }
+        Ok(self.frame_index.as_deref().unwrap_or_default())
+    }
+
+    /// Adds the frames written to a followed file since it was indexed. Only the last indexed
+    /// frame is scanned again, as it may have been incomplete.
+    fn extend_index(&mut self) -> Result<()> {
+        let last = self.frame_index.as_ref().and_then(|e| e.last().copied());
+        let (Some(last), Some(_)) = (last, self.format) else {
+            self.frame_index = Some(self.build_index()?);
+            return Ok(());
+        };
 
-        let matches: Vec<pcre2::bytes::Match> = MAGIC_SEQUENCE
-            .find_iter(&buffer)
-            .filter_map(|x| x.ok())
-            .collect();
+        let frames = self.restoring_position(|this| this.scan_index(last.offset))?;
+        if let (Some(entries), false) = (&mut self.frame_index, frames.is_empty()) {
+            entries.pop();
+            entries.extend(frames);
+        }
+        Ok(())
+    }
 
-        if matches.is_empty() {
-            return Err(anyhow!("No matches found"));
+    /// Returns the byte range of a frame within the file.
+    fn frame_range(&mut self, frame_index: usize) -> Result<(u64, u64)> {
+        // A followed file may have grown since it was scanned.
+        if self.follow.is_some() && frame_index + 1 >= self.frame_entries()?.len() {
+            self.extend_index()?;
         }
 
-        let start = matches[0].start();
+        let entries = self.frame_entries()?;
 
-        if !self.leftover.is_empty() {
-            self.imgs
-                .push([&self.leftover[..], &buffer[..start]].concat());
+        let entry = entries.get(frame_index).ok_or(Error::NoFrameFound {
+            index: frame_index,
+            frame_count: entries.len(),
+        })?;
+
+        Ok((entry.offset, entry.offset + entry.length))
+    }
+
+    /// Uses the sidecar index next to the file if it is present and up to date.
+    /// Returns `false` if there is no usable index.
+    pub fn load_index(&mut self) -> Result<bool> {
+        match FrameIndex::load(&self.path)? {
+            Some(index) => {
+                self.frame_index = Some(index.frames);
+                Ok(true)
+            }
+            None => Ok(false),
         }
+    }
+
+    /// Writes the frame index to a sidecar file next to the CSQ file, scanning the file first
+    /// if necessary. Returns the path of the index file.
+    pub fn write_index(&mut self) -> Result<PathBuf> {
+        let frames = self.frame_entries()?.to_vec();
+        FrameIndex::new(&self.path, frames)?.write(&self.path)
+    }
 
-        if matches[1..].is_empty() {
-            return Err(anyhow!("No more matches found"));
+    /// Loads the sidecar index, or builds and writes it if it is missing or outdated.
+    pub fn load_or_write_index(&mut self) -> Result<()> {
+        if !self.load_index()? {
+            self.write_index()?;
         }
+        Ok(())
+    }
+
+    /// The number of frames in the file. The first call scans the whole file.
+    pub fn frame_count(&mut self) -> Result<usize> {
+        Ok(self.frame_entries()?.len())
+    }
 
-        let mut end: usize = usize::default();
-        for (m1, m2) in matches.iter().zip(matches[1..].iter()) {
-            let start = m1.start();
-            end = m2.start();
+    /// The capture times of all frames, read from the frame headers without decoding the frames.
+    /// The first call scans the whole file, unless the index was loaded.
+    pub fn timeline(&mut self) -> Result<Timeline> {
+        let timestamps = self.frame_entries()?.iter().map(|e| e.timestamp).collect();
+        Ok(Timeline::new(timestamps))
+    }
 
-            let img = buffer[start..end].to_vec();
-            self.imgs.push(img);
+    /// Compares the frame rate the camera was set to with the capture times of the frames, and
+    /// finds where frames were dropped. Like `timeline`, this does not decode any frames.
+    pub fn frame_rate_report(&mut self) -> Result<FrameRateReport> {
+        let timeline = self.timeline()?;
+
+        // The nominal frame rate is taken from the first frame with a readable header.
+        let mut nominal = None;
+        for index in 0..timeline.len() {
+            if let Ok(metadata) = self.read_metadata(index) {
+                nominal = metadata.nominal_frame_rate();
+                break;
+            }
         }
 
-        self.leftover = buffer[end..].to_vec();
+        Ok(timeline.frame_rate_report(nominal))
+    }
+
+    /// Parses the FFF data of the frame at `frame_index` and returns what `f` reads from it,
+    /// given the metadata overrides of the reader. The thermal image is not decoded, and which
+    /// frame `next_frame` returns does not change.
+    fn read_fff<T>(
+        &mut self,
+        frame_index: usize,
+        f: impl FnOnce(FFFData<'_>, &MetadataOverrides) -> Result<T>,
+    ) -> Result<T> {
+        let (_, img) = self.read_frame_bytes(frame_index)?;
+        f(FFFData::parse(&img)?, &self.overrides)
+    }
+
+    /// Reads the metadata of the frame at `frame_index` without decoding its image, and without
+    /// changing which frame `next_frame` returns.
+    pub fn read_metadata(&mut self, frame_index: usize) -> Result<CSQExifData> {
+        self.read_fff(frame_index, |fff, o| fff.metadata_with_overrides(o))
+    }
+
+    /// Reads the photo of the visual camera embedded in the frame at `frame_index`, like
+    /// `read_metadata`. Returns `None` for cameras without a visual camera.
+    pub fn read_visual_image(&mut self, frame_index: usize) -> Result<Option<VisualImage>> {
+        self.read_fff(frame_index, |fff, _| Ok(fff.visual_image()))
+    }
+
+    /// Reads the palette the camera displayed the frame at `frame_index` with, like
+    /// `read_metadata`.
+    pub fn read_palette(&mut self, frame_index: usize) -> Result<Option<Palette>> {
+        self.read_fff(frame_index, |fff, _| Ok(fff.palette))
+    }
+
+    /// Reads the measurement tools the operator placed on the frame at `frame_index`, like
+    /// `read_metadata`.
+    pub fn read_measurements(&mut self, frame_index: usize) -> Result<Vec<Measurement>> {
+        self.read_fff(frame_index, |fff, _| Ok(fff.measurements))
+    }
+
+    /// Reads the offset and data of a frame, and restores the position of the reader afterwards.
+    fn read_frame_bytes(&mut self, frame_index: usize) -> Result<(u64, Vec<u8>)> {
+        let (start, end) = self.frame_range(frame_index)?;
+
+        self.restoring_position(|this| {
+            let mut img = vec![0; (end - start) as usize];
+            this.reader.seek(SeekFrom::Start(start))?;
+            this.reader.read_exact(&mut img)?;
+            Ok((start, img))
+        })
+    }
+
+    /// Moves the reader so the next call to `next_frame` returns the frame at `frame_index`.
+    pub fn seek(&mut self, frame_index: usize) -> Result<()> {
+        let (start, _) = self.frame_range(frame_index)?;
+
+        self.reader.seek(SeekFrom::Start(start))?;
+        self.splitter = self.splitter_at(start);
+        self.next_index = frame_index;
 
         Ok(())
     }
 
-    fn extract_data(&self, im: &[u8]) -> Result<(CSQExifData, Array2<f32>)> {
-        let mut temp_file = NamedTempFile::new()?;
-        temp_file.write_all(im)?;
-        temp_file.flush()?;
+    /// Reads and decodes the frame at `frame_index`. Afterwards `next_frame` continues with
+    /// the frame following it.
+    pub fn read_frame(&mut self, frame_index: usize) -> Result<Frame> {
+        self.read_raw_frame(frame_index)?.into_frame()
+    }
 
-        let binary = Command::new("exiftool")
-            .arg("-b")
-            .arg("-RawThermalImage")
-            .arg(temp_file.path())
-            .output()?
-            .stdout;
+    /// Like `read_frame`, but without converting the raw values to temperatures.
+    pub fn read_raw_frame(&mut self, frame_index: usize) -> Result<RawFrame> {
+        let (start, img) = self.read_frame_bytes(frame_index)?;
 
-        let decoded = decode_jpeg_py(&binary)?;
+        // Only moved once the frame was read, so a failed read keeps the position of `frames`.
+        let end = start + img.len() as u64;
+        self.reader.seek(SeekFrom::Start(end))?;
+        self.splitter = self.splitter_at(end);
+        self.next_index = frame_index + 1;
 
-        let now = Instant::now();
-        let csq_exif_data = match Exif::new(temp_file.path(), Mode::All) {
-            Ok(exif) => {
-                let value = serde_json::to_value(exif.attributes)?;
-                let csq_exif_data: CSQExifData = serde_json::from_value(value)?;
-                let box_exif_data = Box::new(csq_exif_data);
+        Self::extract_data(frame_index, start, &img, &self.overrides)
+    }
 
-                temp_file.close()?;
-                Ok(*box_exif_data)
-            }
-            Err(e) => {
-                temp_file.close()?;
-                Err(anyhow!("Error extracting exif data: {}", e))
-            }
-        }?;
-        println!("creating exif took: {:?}", now.elapsed());
+    pub(crate) fn extract_data(
+        index: usize,
+        offset: u64,
+        im: &[u8],
+        overrides: &MetadataOverrides,
+    ) -> Result<RawFrame> {
+        // Parsing validates the FFF header and record directory before anything is decoded.
+        FFFData::parse(im)?.decode_raw_frame_with_overrides(index, offset, overrides)
+    }
 
-        Ok((csq_exif_data, decoded))
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        self.next_decoded_frame()
     }
 
-    pub fn next_frame(&mut self) -> Result<Option<Box<Array2<f32>>>> {
-        if self.index >= self.imgs.len() {
-            self.populate_list()?;
+    /// Like `next_frame`, but without converting the raw values to temperatures.
+    pub fn next_raw_frame(&mut self) -> Result<Option<RawFrame>> {
+        self.next_decoded_frame()
+    }
 
-            if self.imgs.is_empty() {
+    fn next_decoded_frame<T: DecodedFrame>(&mut self) -> Result<Option<T>> {
+        loop {
+            let Some((index, offset, img)) = self.next_frame_data()? else {
                 return Ok(None);
+            };
+
+            let frame =
+                Self::extract_data(index, offset, &img, &self.overrides).and_then(T::from_raw);
+            if let Some(frame) = self.recover(index, offset, img.len() as u64, frame)? {
+                return Ok(Some(frame));
             }
         }
+    }
 
-        let img = &self.imgs[self.index];
+    /// Applies the recovery policy to the result of decoding a frame. Returns `None` if the
+    /// frame was skipped.
+    pub(crate) fn recover<T: DecodedFrame>(
+        &mut self,
+        index: usize,
+        offset: u64,
+        length: u64,
+        frame: Result<T>,
+    ) -> Result<Option<T>> {
+        let error = match frame {
+            Ok(frame) => {
+                self.last_good = Some((frame.raw().dim(), frame.metadata().clone()));
+                return Ok(Some(frame));
+            }
+            Err(e) if self.recovery == RecoveryPolicy::Abort || !e.is_frame_error() => {
+                return Err(e)
+            }
+            Err(e) => e,
+        };
+
+        self.skipped.push(SkippedFrame {
+            index,
+            offset,
+            length,
+            reason: error.to_string(),
+        });
 
-        let (metadata, decoded) = self.extract_data(img)?;
+        match (&self.last_good, self.recovery) {
+            (Some((shape, metadata)), RecoveryPolicy::Placeholder) => {
+                let placeholder = RawFrame::placeholder(index, offset, *shape, metadata.clone());
+                T::from_raw(placeholder).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
 
-        let temps = raw_to_temp(&decoded, metadata)?;
+    /// Splits off the next frame without decoding it, returning its index, offset and data.
+    pub(crate) fn next_frame_data(&mut self) -> Result<Option<(usize, u64, Vec<u8>)>> {
+        let frame = self.splitter.next_frame(&mut self.reader)?;
+        self.format = self.format.or(self.splitter.detected_format());
+        let Some((offset, img)) = frame else {
+            return Ok(None);
+        };
 
-        self.index += 1;
+        let index = self.next_index;
+        self.next_index += 1;
 
-        Ok(Some(temps))
+        Ok(Some((index, offset, img)))
     }
 
-    pub fn frames(&mut self) -> impl Iterator<Item = Result<Box<Array2<f32>>>> + '_ {
+    pub fn frames(&mut self) -> impl Iterator<Item = Result<Frame>> + '_ {
         std::iter::from_fn(move || match self.next_frame() {
             Ok(Some(frame)) => Some(Ok(frame)),
             Ok(None) => None,
             Err(e) => Some(Err(e)),
         })
     }
+
+    pub fn raw_frames(&mut self) -> impl Iterator<Item = Result<RawFrame>> + '_ {
+        std::iter::from_fn(move || match self.next_raw_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// Like `frames`, but decodes the frames on `threads` worker threads, or one per CPU if
+    /// `threads` is 0. The frames are still returned in the order of the file, and at most a
+    /// few frames per thread are held in memory.
+    pub fn parallel_frames(&mut self, threads: usize) -> Result<ParallelFrames<'_, Frame>> {
+        ParallelFrames::new(self, threads)
+    }
+
+    /// Like `raw_frames`, but decodes the frames on `threads` worker threads.
+    pub fn parallel_raw_frames(&mut self, threads: usize) -> Result<ParallelFrames<'_, RawFrame>> {
+        ParallelFrames::new(self, threads)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fff::tests::{csq_frame, seq_frame};
+
+    #[test]
+    fn failed_scan_keeps_the_position_of_the_reader() {
+        let path = std::env::temp_dir().join(format!("csq-not-a-csq-{}.bin", std::process::id()));
+        std::fs::write(&path, vec![b'x'; 4096]).unwrap();
+
+        let mut reader = CSQReader::new(&path).unwrap();
+        let count = reader.frame_count();
+        let next = reader.frames().next();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(count, Err(Error::NotACsqFile(_))));
+        assert!(matches!(next, Some(Err(Error::NotACsqFile(_)))));
+    }
+
+    #[test]
+    fn followed_file_is_indexed_incrementally() {
+        let frame = |fill: u8| [b"FFF\0RT".as_slice(), &[fill; 64]].concat();
+        let path = std::env::temp_dir().join(format!("csq-follow-{}.csq", std::process::id()));
+        std::fs::write(&path, [frame(1), frame(2)].concat()).unwrap();
+
+        let mut reader = CSQReader::new(&path).unwrap();
+        reader.set_follow(Some(Duration::ZERO));
+        assert_eq!(reader.frame_count().unwrap(), 2);
+
+        // A full rescan would lose this timestamp, as the frames have no readable header.
+        let timestamp = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap();
+        if let Some(entries) = &mut reader.frame_index {
+            entries[0].timestamp = Some(timestamp);
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(&mut file, &[frame(3), frame(4)].concat()).unwrap();
+
+        let range = reader.frame_range(3);
+        let entries = reader.frame_index.clone().unwrap_or_default();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(range.unwrap(), (210, 280));
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].timestamp, Some(timestamp));
+        assert_eq!((entries[1].offset, entries[1].length), (70, 70));
+    }
+
+    #[test]
+    fn failed_read_keeps_the_position_of_the_reader() {
+        let frames = [csq_frame(&[1000, 2000]), csq_frame(&[3000, 4000])];
+        let path = std::env::temp_dir().join(format!("csq-truncated-{}.csq", std::process::id()));
+        std::fs::write(&path, frames.concat()).unwrap();
+
+        let mut reader = CSQReader::new(&path).unwrap();
+        assert_eq!(reader.frame_count().unwrap(), 2);
+
+        // The file is truncated after it was indexed, so the second frame cannot be read.
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len((frames[0].len() + frames[1].len() / 2) as u64)
+            .unwrap();
+
+        let failed = reader.read_raw_frame(1);
+        let next = reader.next_raw_frame();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(failed, Err(Error::Io(_))));
+        let next = next.unwrap().unwrap();
+        assert_eq!((next.index, next.offset), (0, 0));
+        assert_eq!(next.raw, ndarray::arr2(&[[1000, 2000]]));
+    }
+
+    /// Writes three frames to a file, the middle one cut off halfway, and opens it with
+    /// `policy`. Returns the reader, the path and the offset and length of the damaged frame.
+    pub(crate) fn damaged_file(policy: RecoveryPolicy) -> (CSQReader, PathBuf, u64, u64) {
+        let mut damaged = csq_frame(&[3000, 4000]);
+        damaged.truncate(damaged.len() / 2);
+        let first = csq_frame(&[1000, 2000]);
+        let data = [first.clone(), damaged.clone(), csq_frame(&[5000, 6000])].concat();
+
+        let path = std::env::temp_dir().join(format!(
+            "csq-damaged-{:?}-{}.csq",
+            policy,
+            std::process::id()
+        ));
+        std::fs::write(&path, data).unwrap();
+
+        let mut reader = CSQReader::new(&path).unwrap();
+        reader.set_recovery_policy(policy);
+        (reader, path, first.len() as u64, damaged.len() as u64)
+    }
+
+    #[test]
+    fn damaged_frame_is_returned_as_error_with_abort() {
+        let (mut reader, path, _, _) = damaged_file(RecoveryPolicy::Abort);
+        let frames: Vec<_> = reader.raw_frames().collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(frames[0], Ok(RawFrame { index: 0, .. })));
+        assert!(matches!(&frames[1], Err(e) if e.is_frame_error()));
+        assert!(reader.skipped_frames().is_empty());
+    }
+
+    #[test]
+    fn damaged_frame_is_skipped() {
+        let (mut reader, path, offset, length) = damaged_file(RecoveryPolicy::Skip);
+        let frames: Vec<_> = reader.raw_frames().collect::<Result<_>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let indices: Vec<usize> = frames.iter().map(|f| f.index).collect();
+        assert_eq!(indices, [0, 2]);
+        assert_eq!(frames[1].raw, ndarray::arr2(&[[5000, 6000]]));
+
+        let [skipped] = reader.skipped_frames() else {
+            panic!("expected one skipped frame: {:?}", reader.skipped_frames());
+        };
+        assert_eq!(
+            (skipped.index, skipped.offset, skipped.length),
+            (1, offset, length)
+        );
+    }
+
+    #[test]
+    fn damaged_frame_is_replaced_by_a_placeholder() {
+        let (mut reader, path, offset, _) = damaged_file(RecoveryPolicy::Placeholder);
+        let frames: Vec<_> = reader.frames().collect::<Result<_>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let placeholders: Vec<bool> = frames.iter().map(|f| f.placeholder).collect();
+        assert_eq!(placeholders, [false, true, false]);
+        assert_eq!((frames[1].index, frames[1].offset), (1, offset));
+        assert_eq!(frames[1].raw, ndarray::arr2(&[[0, 0]]));
+        assert!(frames[1].temperatures.iter().all(|t| t.is_nan()));
+        assert_eq!(reader.skipped_frames().len(), 1);
+    }
+
+    #[test]
+    fn seq_files_are_read_like_csq_files() {
+        let frames = [seq_frame(&[1000, 2000]), seq_frame(&[3000, 4000])];
+        let path = std::env::temp_dir().join(format!("csq-legacy-{}.seq", std::process::id()));
+        std::fs::write(&path, frames.concat()).unwrap();
+
+        let mut reader = CSQReader::new(&path).unwrap();
+        let format = reader.format();
+        let count = reader.frame_count();
+        let last = reader.read_raw_frame(1);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(format, Some(FileFormat::Seq));
+        assert_eq!(count.unwrap(), 2);
+        let last = last.unwrap();
+        assert_eq!(last.offset, frames[0].len() as u64);
+        assert_eq!(last.raw, ndarray::arr2(&[[3000, 4000]]));
+    }
 }
@@ -0,0 +1,48 @@
+use thiserror::Error;
+
+/// Errors returned when reading CSQ files.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a CSQ file: {0}")]
+    NotACsqFile(String),
+    #[error("Not a radiometric JPEG: {0}")]
+    NotARadiometricJpeg(String),
+    #[error("No frame at index {index}, the file has {frame_count} frames")]
+    NoFrameFound { index: usize, frame_count: usize },
+    #[error("Truncated frame: {0}")]
+    TruncatedFrame(String),
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+    #[error("Missing metadata: {0}")]
+    MetadataMissing(String),
+    #[error("Invalid metadata value for {field}: {value:?}")]
+    InvalidMetadata { field: String, value: String },
+    #[error("Failed to decode raw thermal image: {0}")]
+    DecodeFailed(String),
+    #[error("Unsupported raw thermal image type: {0}")]
+    UnsupportedRawImageType(String),
+    #[error("Unsupported image type: {0}")]
+    UnsupportedImageType(String),
+    #[error("Failed to create thread pool: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+impl Error {
+    /// Whether the error is caused by the data of a single frame, as opposed to the file as a
+    /// whole, so reading can continue with the next frame.
+    pub fn is_frame_error(&self) -> bool {
+        matches!(
+            self,
+            Error::TruncatedFrame(_)
+                | Error::InvalidFrame(_)
+                | Error::MetadataMissing(_)
+                | Error::InvalidMetadata { .. }
+                | Error::DecodeFailed(_)
+                | Error::UnsupportedRawImageType(_)
+        )
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,1219 @@
+use crate::error::{Error, Result};
+use crate::measurement::{self, LocalParameters, Measurement};
+use crate::palette::{ycrcb_to_rgb, Palette};
+use crate::raw;
+use crate::types::{CSQExifData, Frame, MetadataOverrides, RawFrame};
+use crate::visual::VisualImage;
+use chrono::{DateTime, FixedOffset};
+use ndarray::Array2;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+pub const HEADER_SIZE: usize = 0x40;
+const DIRECTORY_ENTRY_SIZE: usize = 0x20;
+const RAW_DATA_HEADER_SIZE: usize = 0x20;
+const EMBEDDED_IMAGE_HEADER_SIZE: usize = 0x20;
+const PALETTE_DATA_OFFSET: usize = 0x70;
+const MEAS_INFO_HEADER_SIZE: usize = 0x0c;
+const MEAS_TOOL_HEADER_SIZE: usize = 0x24;
+const MEAS_LABEL_SIZE: usize = 0x20;
+const CAMERA_INFO_DATE_END: usize = 0x38e;
+const MAX_DIRECTORY_ENTRIES: usize = 0x400;
+
+/// The exiftool formatting of `Date/TimeOriginal`, with milliseconds and the time zone.
+pub const DATE_TIME_FORMAT: &str = "%Y:%m:%d %H:%M:%S%.3f%:z";
+
+const RECORD_RAW_DATA: u16 = 0x01;
+const RECORD_EMBEDDED_IMAGE: u16 = 0x0e;
+const RECORD_CAMERA_INFO: u16 = 0x20;
+const RECORD_MEAS_INFO: u16 = 0x21;
+const RECORD_PALETTE_INFO: u16 = 0x22;
+const RECORD_PIP: u16 = 0x2a;
+const RECORD_GPS_INFO: u16 = 0x2b;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn toggled(self) -> Self {
+        match self {
+            ByteOrder::Little => ByteOrder::Big,
+            ByteOrder::Big => ByteOrder::Little,
+        }
+    }
+}
+
+/// Bounds checked reads of fixed width values from a FFF record.
+#[derive(Clone, Copy)]
+struct RecordReader<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+}
+
+impl<'a> RecordReader<'a> {
+    /// FLIR records start with a 16 bit value of 2 which is used to detect the byte order.
+    fn detect(data: &'a [u8], order: ByteOrder) -> Result<Self> {
+        let reader = Self { data, order };
+        if reader.u16(0)? >= 0x100 {
+            return Ok(Self {
+                data,
+                order: order.toggled(),
+            });
+        }
+        Ok(reader)
+    }
+
+    fn bytes<const N: usize>(&self, pos: usize) -> Result<[u8; N]> {
+        self.data
+            .get(pos..pos + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| {
+                Error::TruncatedFrame(format!(
+                    "FFF record too short to read {} bytes at {:#x}",
+                    N, pos
+                ))
+            })
+    }
+
+    fn u16(&self, pos: usize) -> Result<u16> {
+        let b = self.bytes::<2>(pos)?;
+        Ok(match self.order {
+            ByteOrder::Little => u16::from_le_bytes(b),
+            ByteOrder::Big => u16::from_be_bytes(b),
+        })
+    }
+
+    fn i16(&self, pos: usize) -> Result<i16> {
+        Ok(self.u16(pos)? as i16)
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32> {
+        let b = self.bytes::<4>(pos)?;
+        Ok(match self.order {
+            ByteOrder::Little => u32::from_le_bytes(b),
+            ByteOrder::Big => u32::from_be_bytes(b),
+        })
+    }
+
+    fn i32(&self, pos: usize) -> Result<i32> {
+        Ok(self.u32(pos)? as i32)
+    }
+
+    fn f32(&self, pos: usize) -> Result<f32> {
+        Ok(f32::from_bits(self.u32(pos)?))
+    }
+
+    fn f64(&self, pos: usize) -> Result<f64> {
+        let b = self.bytes::<8>(pos)?;
+        Ok(match self.order {
+            ByteOrder::Little => f64::from_le_bytes(b),
+            ByteOrder::Big => f64::from_be_bytes(b),
+        })
+    }
+
+    fn string(&self, pos: usize, len: usize) -> Result<String> {
+        let b = self.data.get(pos..pos + len).ok_or_else(|| {
+            Error::TruncatedFrame(format!("FFF record too short to read string at {:#x}", pos))
+        })?;
+        let end = b.iter().position(|&c| c == 0).unwrap_or(len);
+        Ok(String::from_utf8_lossy(&b[..end]).trim().to_string())
+    }
+}
+
+/// A single entry of the FFF record directory.
+#[derive(Debug, Clone, Copy)]
+struct DirectoryEntry {
+    kind: u16,
+    offset: usize,
+    length: usize,
+}
+
+/// A record together with its data.
+#[derive(Debug, Clone, Copy)]
+struct Record<'a> {
+    kind: u16,
+    data: &'a [u8],
+    /// The byte order of the header, for records that do not start with a byte order mark.
+    order: ByteOrder,
+}
+
+/// The contents of one FLIR FFF container, as found at every `MAGIC_SEQUENCE` in a CSQ file,
+/// in standalone .fff files and in radiometric JPEGs.
+///
+/// The metadata is collected into `tags`, using the same names and display formatting as
+/// exiftool so it can be deserialized into `CSQExifData`.
+#[derive(Debug, Clone)]
+pub struct FFFData<'a> {
+    pub raw_thermal_image: &'a [u8],
+    /// The byte order of the RawData record, which uncompressed images are stored in.
+    pub(crate) raw_byte_order: ByteOrder,
+    /// The encoded photo of the visual camera, if the camera has one.
+    pub embedded_image: Option<&'a [u8]>,
+    /// The palette the camera displayed the thermal image with.
+    pub palette: Option<Palette>,
+    /// The measurement tools the operator placed on the image.
+    pub measurements: Vec<Measurement>,
+    pub capture_time: Option<DateTime<FixedOffset>>,
+    pub tags: HashMap<String, String>,
+}
+
+impl<'a> FFFData<'a> {
+    /// Parses the header, record directory and metadata records, without decoding the raw
+    /// thermal image yet.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let mut tags = HashMap::new();
+
+        let records = read_directory(data, &mut tags)?;
+
+        let raw_data = records
+            .iter()
+            .find(|r| r.kind == RECORD_RAW_DATA)
+            .ok_or_else(|| Error::InvalidFrame("FFF data contains no RawData record".into()))?;
+        let (raw_thermal_image, raw_byte_order) = parse_raw_data(raw_data.data, &mut tags)?;
+
+        // Only the RawData record is required, the calibration of frames without a complete
+        // CameraInfo record is resolved by `CSQExifData::from_tags_with_overrides`.
+        let capture_time = records
+            .iter()
+            .find(|r| r.kind == RECORD_CAMERA_INFO)
+            .and_then(|r| parse_camera_info(r.data, &mut tags));
+
+        let embedded_image = records
+            .iter()
+            .find(|r| r.kind == RECORD_EMBEDDED_IMAGE)
+            .map(|r| parse_embedded_image(r.data, &mut tags))
+            .transpose()?;
+
+        let palette = records
+            .iter()
+            .find(|r| r.kind == RECORD_PALETTE_INFO)
+            .map(|r| parse_palette_info(r.data, &mut tags))
+            .transpose()?;
+
+        let measurements = records
+            .iter()
+            .find(|r| r.kind == RECORD_MEAS_INFO)
+            .map(|r| parse_meas_info(r, &mut tags))
+            .transpose()?
+            .unwrap_or_default();
+
+        if let Some(pip) = records.iter().find(|r| r.kind == RECORD_PIP) {
+            parse_pip(pip, &mut tags)?;
+        }
+
+        if let Some(gps) = records.iter().find(|r| r.kind == RECORD_GPS_INFO) {
+            parse_gps_info(gps, &mut tags)?;
+        }
+
+        tags.insert("FileType".into(), "FFF".into());
+        tags.insert("FileTypeExtension".into(), "fff".into());
+        tags.insert("MIMEType".into(), "image/x-flir-fff".into());
+
+        Ok(Self {
+            raw_thermal_image,
+            raw_byte_order,
+            embedded_image,
+            palette,
+            measurements,
+            capture_time,
+            tags,
+        })
+    }
+
+    /// The photo of the visual camera, with its size and format.
+    pub fn visual_image(&self) -> Option<VisualImage> {
+        let data = self.embedded_image?;
+        let dimension = |key: &str| self.tags.get(key).and_then(|v| v.parse().ok());
+
+        Some(VisualImage {
+            width: dimension("EmbeddedImageWidth")?,
+            height: dimension("EmbeddedImageHeight")?,
+            image_type: self.tags.get("EmbeddedImageType")?.clone(),
+            data: data.to_vec(),
+        })
+    }
+
+    pub fn metadata(&self) -> Result<CSQExifData> {
+        CSQExifData::from_tags(&self.tags)
+    }
+
+    /// Like `metadata`, with `overrides` for the parameters the file lacks.
+    pub fn metadata_with_overrides(&self, overrides: &MetadataOverrides) -> Result<CSQExifData> {
+        CSQExifData::from_tags_with_overrides(&self.tags, overrides)
+    }
+
+    /// Decodes the raw thermal image, and checks that its size matches the RawData record.
+    pub fn decode_raw(&self) -> Result<Array2<u16>> {
+        let dimension = |key: &str| -> Result<usize> {
+            let value = self
+                .tags
+                .get(key)
+                .ok_or_else(|| Error::MetadataMissing(key.to_string()))?;
+            value.parse().map_err(|_| Error::InvalidMetadata {
+                field: key.to_string(),
+                value: value.clone(),
+            })
+        };
+        let width = dimension("RawThermalImageWidth")?;
+        let height = dimension("RawThermalImageHeight")?;
+
+        let raw = raw::decode(self, width, height)?;
+
+        if raw.dim() != (height, width) {
+            return Err(Error::InvalidFrame(format!(
+                "Raw thermal image is {}x{}, but the RawData record says {}x{}",
+                raw.ncols(),
+                raw.nrows(),
+                width,
+                height
+            )));
+        }
+
+        Ok(raw)
+    }
+
+    /// Decodes the container as the frame at `index` and byte `offset` of its file.
+    pub fn decode_raw_frame(&self, index: usize, offset: u64) -> Result<RawFrame> {
+        self.decode_raw_frame_with_overrides(index, offset, &MetadataOverrides::default())
+    }
+
+    /// Like `decode_raw_frame`, with `overrides` for the parameters the file lacks.
+    pub fn decode_raw_frame_with_overrides(
+        &self,
+        index: usize,
+        offset: u64,
+        overrides: &MetadataOverrides,
+    ) -> Result<RawFrame> {
+        // The metadata is checked first, as it is needed to convert the frame to temperatures.
+        let metadata = self.metadata_with_overrides(overrides)?;
+        let raw = self.decode_raw()?;
+
+        Ok(RawFrame {
+            index,
+            offset,
+            timestamp: self.capture_time,
+            raw,
+            metadata: Arc::new(metadata),
+            placeholder: false,
+        })
+    }
+}
+
+/// Reads a standalone .fff file, as written by FLIR Research Studio and the FLIR SDKs, and
+/// converts it to temperatures.
+pub fn read_fff(path: &Path) -> Result<Frame> {
+    decode_fff(&fs::read(path)?)
+}
+
+/// Decodes a single FFF container, e.g. a frame of a CSQ file, and converts it to temperatures.
+pub fn decode_fff(data: &[u8]) -> Result<Frame> {
+    FFFData::parse(data)?.decode_raw_frame(0, 0)?.into_frame()
+}
+
+/// The fixed size header at the start of every FFF container.
+struct Header {
+    order: ByteOrder,
+    creator_software: String,
+    dir_offset: usize,
+    entries: usize,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE || !(data.starts_with(b"FFF\0") || data.starts_with(b"AFF\0")) {
+            return Err(Error::InvalidFrame("Not a FFF header".into()));
+        }
+
+        // The byte order is not flagged in the header, but the format version is always
+        // in the range 100..200.
+        let mut r = RecordReader {
+            data,
+            order: ByteOrder::Little,
+        };
+        if !(100..200).contains(&r.u32(0x14)?) {
+            r.order = ByteOrder::Big;
+            if !(100..200).contains(&r.u32(0x14)?) {
+                return Err(Error::InvalidFrame("Unsupported FFF version".into()));
+            }
+        }
+
+        let entries = r.u32(0x1c)? as usize;
+        if entries > MAX_DIRECTORY_ENTRIES {
+            return Err(Error::InvalidFrame(format!(
+                "Implausible FFF record count {}",
+                entries
+            )));
+        }
+
+        Ok(Self {
+            order: r.order,
+            creator_software: r.string(0x04, 16)?,
+            dir_offset: r.u32(0x18)? as usize,
+            entries,
+        })
+    }
+
+    /// Reads the record directory, `directory` has to start at `dir_offset`.
+    fn directory(&self, directory: &[u8]) -> Result<Vec<DirectoryEntry>> {
+        let r = RecordReader {
+            data: directory,
+            order: self.order,
+        };
+
+        let mut entries = Vec::with_capacity(self.entries);
+        for i in 0..self.entries {
+            let pos = i * DIRECTORY_ENTRY_SIZE;
+            let kind = r.u16(pos)?;
+            if kind == 0 {
+                continue;
+            }
+
+            entries.push(DirectoryEntry {
+                kind,
+                offset: r.u32(pos + 0x0c)? as usize,
+                length: r.u32(pos + 0x10)? as usize,
+            });
+        }
+
+        Ok(entries)
+    }
+}
+
+fn read_directory<'a>(
+    data: &'a [u8],
+    tags: &mut HashMap<String, String>,
+) -> Result<Vec<Record<'a>>> {
+    let header = Header::parse(data)?;
+
+    if !header.creator_software.is_empty() {
+        tags.insert("CreatorSoftware".into(), header.creator_software.clone());
+    }
+
+    let directory = data
+        .get(header.dir_offset..)
+        .ok_or_else(|| Error::TruncatedFrame("FFF record directory beyond end of data".into()))?;
+
+    header
+        .directory(directory)?
+        .into_iter()
+        .map(|entry| {
+            data.get(entry.offset..entry.offset + entry.length)
+                .map(|data| Record {
+                    kind: entry.kind,
+                    data,
+                    order: header.order,
+                })
+                .ok_or_else(|| {
+                    Error::TruncatedFrame(format!(
+                        "FFF record {:#x} extends beyond end of data",
+                        entry.kind
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// Whether `data` starts with a valid FFF header.
+pub fn is_header(data: &[u8]) -> bool {
+    Header::parse(data).is_ok()
+}
+
+/// Reads the capture time of the FFF container at `offset` without loading its image data.
+pub fn read_capture_time<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+) -> Result<Option<DateTime<FixedOffset>>> {
+    let mut header = [0; HEADER_SIZE];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut header)?;
+    let header = Header::parse(&header)?;
+
+    let mut directory = vec![0; header.entries * DIRECTORY_ENTRY_SIZE];
+    reader.seek(SeekFrom::Start(offset + header.dir_offset as u64))?;
+    reader.read_exact(&mut directory)?;
+
+    let Some(camera_info) = header
+        .directory(&directory)?
+        .into_iter()
+        .find(|e| e.kind == RECORD_CAMERA_INFO)
+    else {
+        return Ok(None);
+    };
+
+    let mut record = vec![0; camera_info.length.min(CAMERA_INFO_DATE_END)];
+    reader.seek(SeekFrom::Start(offset + camera_info.offset as u64))?;
+    reader.read_exact(&mut record)?;
+
+    date_time_original(&RecordReader::detect(&record, ByteOrder::Little)?)
+}
+
+fn parse_raw_data<'a>(
+    data: &'a [u8],
+    tags: &mut HashMap<String, String>,
+) -> Result<(&'a [u8], ByteOrder)> {
+    let r = RecordReader::detect(data, ByteOrder::Little)?;
+
+    tags.insert("RawThermalImageWidth".into(), r.u16(0x02)?.to_string());
+    tags.insert("RawThermalImageHeight".into(), r.u16(0x04)?.to_string());
+
+    let image = data
+        .get(RAW_DATA_HEADER_SIZE..)
+        .ok_or_else(|| Error::TruncatedFrame("RawData record too short".into()))?;
+
+    let image_type = if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "PNG"
+    } else if image.starts_with(b"\xff\xd8\xff") {
+        "JPG"
+    } else {
+        "TIFF"
+    };
+    tags.insert("RawThermalImageType".into(), image_type.into());
+    tags.insert(
+        "RawThermalImage".into(),
+        format!(
+            "(Binary data {} bytes, use -b option to extract)",
+            image.len()
+        ),
+    );
+
+    Ok((image, r.order))
+}
+
+fn parse_embedded_image<'a>(
+    data: &'a [u8],
+    tags: &mut HashMap<String, String>,
+) -> Result<&'a [u8]> {
+    let r = RecordReader::detect(data, ByteOrder::Little)?;
+
+    tags.insert("EmbeddedImageWidth".into(), r.u16(0x02)?.to_string());
+    tags.insert("EmbeddedImageHeight".into(), r.u16(0x04)?.to_string());
+
+    let image = data
+        .get(EMBEDDED_IMAGE_HEADER_SIZE..)
+        .ok_or_else(|| Error::TruncatedFrame("EmbeddedImage record too short".into()))?;
+
+    let image_type = if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "PNG"
+    } else if image.starts_with(b"\xff\xd8\xff") {
+        "JPG"
+    } else {
+        "DAT"
+    };
+    tags.insert("EmbeddedImageType".into(), image_type.into());
+    tags.insert(
+        "EmbeddedImage".into(),
+        format!(
+            "(Binary data {} bytes, use -b option to extract)",
+            image.len()
+        ),
+    );
+
+    Ok(image)
+}
+
+/// The PaletteInfo record holds single bytes only, so it has no byte order. The colors are
+/// stored as Y, Cr and Cb.
+fn parse_palette_info(data: &[u8], tags: &mut HashMap<String, String>) -> Result<Palette> {
+    let r = RecordReader {
+        data,
+        order: ByteOrder::Little,
+    };
+    let color = |pos: usize| r.bytes::<3>(pos);
+    let format_color = |c: [u8; 3]| format!("{} {} {}", c[0], c[1], c[2]);
+
+    let count = r.bytes::<1>(0x00)?[0] as usize;
+    let table = data
+        .get(PALETTE_DATA_OFFSET..PALETTE_DATA_OFFSET + count * 3)
+        .ok_or_else(|| Error::TruncatedFrame("PaletteInfo record too short".into()))?;
+
+    tags.insert("PaletteColors".into(), count.to_string());
+    for (key, pos) in [
+        ("AboveColor", 0x06),
+        ("BelowColor", 0x09),
+        ("OverflowColor", 0x0c),
+        ("UnderflowColor", 0x0f),
+        ("Isotherm1Color", 0x12),
+        ("Isotherm2Color", 0x15),
+    ] {
+        tags.insert(key.into(), format_color(color(pos)?));
+    }
+    tags.insert("PaletteMethod".into(), r.bytes::<1>(0x1a)?[0].to_string());
+    tags.insert("PaletteStretch".into(), r.bytes::<1>(0x1b)?[0].to_string());
+
+    let name = r.string(0x50, 32)?;
+    for (key, value) in [
+        ("PaletteFileName", r.string(0x30, 32)?),
+        ("PaletteName", name.clone()),
+    ] {
+        if !value.is_empty() {
+            tags.insert(key.into(), value);
+        }
+    }
+    tags.insert(
+        "Palette".into(),
+        format!(
+            "(Binary data {} bytes, use -b option to extract)",
+            table.len()
+        ),
+    );
+
+    Ok(Palette {
+        name,
+        colors: table
+            .chunks_exact(3)
+            .map(|c| ycrcb_to_rgb([c[0], c[1], c[2]]))
+            .collect(),
+        above_color: ycrcb_to_rgb(color(0x06)?),
+        below_color: ycrcb_to_rgb(color(0x09)?),
+        overflow_color: ycrcb_to_rgb(color(0x0c)?),
+        underflow_color: ycrcb_to_rgb(color(0x0f)?),
+        isotherm1_color: ycrcb_to_rgb(color(0x12)?),
+        isotherm2_color: ycrcb_to_rgb(color(0x15)?),
+    })
+}
+
+/// The MeasInfo record holds one entry per measurement tool after a short header. Every entry
+/// starts with its length and the length of its coordinates, followed by the tool type, the
+/// local object parameters, the coordinates and a UTF-16 label.
+fn parse_meas_info(
+    record: &Record,
+    tags: &mut HashMap<String, String>,
+) -> Result<Vec<Measurement>> {
+    let r = RecordReader::detect(record.data, record.order)?;
+
+    let mut measurements = vec![];
+    let mut pos = MEAS_INFO_HEADER_SIZE;
+    while let Ok(len) = r.u16(pos) {
+        let len = len as usize;
+        if len < MEAS_TOOL_HEADER_SIZE + 4 || pos + len > record.data.len() {
+            break;
+        }
+
+        let coordinates_len = r.u16(pos + 0x04)? as usize;
+        let tool_type = r.u16(pos + 0x0a)?;
+        let coordinates_start = pos + MEAS_TOOL_HEADER_SIZE;
+        if coordinates_start + coordinates_len > pos + len {
+            break;
+        }
+
+        let params = (0..coordinates_len / 2)
+            .map(|i| r.i16(coordinates_start + i * 2))
+            .collect::<Result<Vec<_>>>()?;
+
+        // The label is null terminated unless it fills all of its space.
+        let label_start = coordinates_start + coordinates_len;
+        let label: Vec<u16> = (0..MEAS_LABEL_SIZE / 2)
+            .map_while(|i| r.u16(label_start + i * 2).ok())
+            .take_while(|&c| c != 0)
+            .collect();
+        let label = String::from_utf16_lossy(&label);
+
+        let local_parameters = (r.u16(pos + 0x0c)? != 0)
+            .then(|| -> Result<_> {
+                Ok(LocalParameters {
+                    emissivity: r.f32(pos + 0x10)?,
+                    object_distance: r.f32(pos + 0x14)?,
+                    reflected_apparent_temperature: r.f32(pos + 0x18)? - 273.15,
+                })
+            })
+            .transpose()?;
+
+        let prefix = format!("Meas{}", measurements.len() + 1);
+        let tool_type_name = measurement::tool_type_name(tool_type);
+        tags.insert(format!("{}Type", prefix), tool_type_name.clone());
+        tags.insert(
+            format!("{}Params", prefix),
+            params
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        if !label.is_empty() {
+            tags.insert(format!("{}Label", prefix), label.clone());
+        }
+
+        measurements.push(Measurement {
+            label,
+            tool_type: tool_type_name,
+            shape: measurement::shape(tool_type, &params),
+            params,
+            local_parameters,
+        });
+
+        pos += len;
+    }
+
+    Ok(measurements)
+}
+
+/// The PiP record describes where the thermal image lies within the visual image. Unlike most
+/// records it starts with a float, so it is read in the byte order of the header.
+fn parse_pip(record: &Record, tags: &mut HashMap<String, String>) -> Result<()> {
+    let r = RecordReader {
+        data: record.data,
+        order: record.order,
+    };
+
+    tags.insert("Real2IR".into(), r.f32(0x00)?.to_string());
+    for (key, pos) in [
+        ("OffsetX", 0x04),
+        ("OffsetY", 0x06),
+        ("PiPX1", 0x08),
+        ("PiPX2", 0x0a),
+        ("PiPY1", 0x0c),
+        ("PiPY2", 0x0e),
+    ] {
+        tags.insert(key.into(), r.i16(pos)?.to_string());
+    }
+
+    Ok(())
+}
+
+/// Adds the tags of the GPSInfo record, with the coordinates formatted like exiftool, e.g.
+/// `52 deg 31' 12.00" N`.
+fn parse_gps_info(record: &Record, tags: &mut HashMap<String, String>) -> Result<()> {
+    let r = RecordReader::detect(record.data, record.order)?;
+
+    let mut insert = |key: &str, value: String| {
+        if !value.is_empty() {
+            tags.insert(key.to_string(), value);
+        }
+    };
+
+    let valid = if r.u32(0x00)? == 1 {
+        "Valid"
+    } else {
+        "Invalid"
+    };
+    insert("GPSValid", valid.into());
+    insert(
+        "GPSVersionID",
+        r.bytes::<4>(0x04)?.map(|b| b.to_string()).join("."),
+    );
+
+    // Some cameras store the hemisphere in the reference only, others in the sign as well.
+    let coordinate = |pos: usize, reference: &str, negative: &str| -> Result<f64> {
+        let value = r.f64(pos)?;
+        Ok(if reference.starts_with(negative) {
+            -value.abs()
+        } else {
+            value
+        })
+    };
+    let latitude_ref = r.string(0x08, 2)?;
+    let longitude_ref = r.string(0x0a, 2)?;
+    let latitude = coordinate(0x10, &latitude_ref, "S")?;
+    let longitude = coordinate(0x18, &longitude_ref, "W")?;
+    let latitude = format_dms(latitude, "N", "S");
+    let longitude = format_dms(longitude, "E", "W");
+
+    insert("GPSLatitudeRef", latitude_ref);
+    insert("GPSLongitudeRef", longitude_ref);
+    insert("GPSPosition", format!("{}, {}", latitude, longitude));
+    insert("GPSLatitude", latitude);
+    insert("GPSLongitude", longitude);
+    insert("GPSAltitude", format!("{} m", r.f32(0x20)?));
+    insert("GPSDilutionOfPrecision", r.f32(0x40)?.to_string());
+    insert("GPSImgDirectionRef", r.string(0x48, 2)?);
+    insert("GPSImgDirection", r.f32(0x54)?.to_string());
+    insert("GPSMapDatum", r.string(0x58, 16)?);
+
+    Ok(())
+}
+
+/// Formats signed decimal degrees as degrees, minutes and seconds with the hemisphere.
+pub(crate) fn format_dms(value: f64, positive: &str, negative: &str) -> String {
+    let hemisphere = if value < 0.0 { negative } else { positive };
+    let value = value.abs();
+    let degrees = value.trunc();
+    let minutes = ((value - degrees) * 60.0).trunc();
+    let seconds = (value - degrees - minutes / 60.0) * 3600.0;
+    format!(
+        "{} deg {}' {:.2}\" {}",
+        degrees, minutes, seconds, hemisphere
+    )
+}
+
+/// Adds the tags of the CameraInfo record and returns the capture time.
+///
+/// Fields beyond the end of a short record are left out rather than failing the frame, so the
+/// calibration can still come from overrides or the FLIR defaults.
+fn parse_camera_info(
+    data: &[u8],
+    tags: &mut HashMap<String, String>,
+) -> Option<DateTime<FixedOffset>> {
+    let r = RecordReader::detect(data, ByteOrder::Little).ok()?;
+
+    let mut insert = |key: &str, value: Option<String>| {
+        if let Some(value) = value {
+            tags.insert(key.to_string(), value);
+        }
+    };
+    let float = |pos: usize, format: fn(f32) -> String| r.f32(pos).ok().map(format);
+    let kelvin = |pos: usize| float(pos, |t| format!("{:.1} C", t - 273.15));
+    let fraction = |pos: usize| float(pos, |v| format!("{:.2}", v));
+    let coefficient = |pos: usize| float(pos, |v| format!("{:.6}", v));
+    let plain = |pos: usize| float(pos, |v| v.to_string());
+
+    insert("Emissivity", fraction(0x20));
+    insert("ObjectDistance", float(0x24, |d| format!("{:.2} m", d)));
+    insert("ReflectedApparentTemperature", kelvin(0x28));
+    insert("AtmosphericTemperature", kelvin(0x2c));
+    insert("IRWindowTemperature", kelvin(0x30));
+    insert("IRWindowTransmission", fraction(0x34));
+    insert(
+        "RelativeHumidity",
+        float(0x3c, |humidity| {
+            let humidity = if humidity > 2.0 {
+                humidity / 100.0
+            } else {
+                humidity
+            };
+            format!("{:.1} %", humidity * 100.0)
+        }),
+    );
+
+    insert("PlanckR1", plain(0x58));
+    insert("PlanckB", plain(0x5c));
+    insert("PlanckF", plain(0x60));
+
+    insert("AtmosphericTransAlpha1", coefficient(0x70));
+    insert("AtmosphericTransAlpha2", coefficient(0x74));
+    insert("AtmosphericTransBeta1", coefficient(0x78));
+    insert("AtmosphericTransBeta2", coefficient(0x7c));
+    insert("AtmosphericTransX", coefficient(0x80));
+
+    insert("CameraTemperatureRangeMax", kelvin(0x90));
+    insert("CameraTemperatureRangeMin", kelvin(0x94));
+    insert("CameraTemperatureMaxClip", kelvin(0x98));
+    insert("CameraTemperatureMinClip", kelvin(0x9c));
+    insert("CameraTemperatureMaxWarn", kelvin(0xa0));
+    insert("CameraTemperatureMinWarn", kelvin(0xa4));
+    insert("CameraTemperatureMaxSaturated", kelvin(0xa8));
+    insert("CameraTemperatureMinSaturated", kelvin(0xac));
+
+    for (key, pos, len) in [
+        ("CameraModel", 0xd4, 32),
+        ("CameraPartNumber", 0xf4, 16),
+        ("CameraSerialNumber", 0x104, 16),
+        ("CameraSoftware", 0x114, 16),
+        ("LensModel", 0x170, 32),
+        ("LensPartNumber", 0x190, 16),
+        ("LensSerialNumber", 0x1a0, 16),
+        ("FilterModel", 0x1ec, 16),
+        ("FilterPartNumber", 0x1fc, 32),
+        ("FilterSerialNumber", 0x21c, 32),
+    ] {
+        insert(key, r.string(pos, len).ok().filter(|v| !v.is_empty()));
+    }
+
+    insert("FieldOfView", float(0x1b4, |f| format!("{:.1} deg", f)));
+
+    insert("PlanckO", r.i32(0x308).ok().map(|v| v.to_string()));
+    insert("PlanckR2", plain(0x30c));
+    for (key, pos) in [
+        ("RawValueRangeMin", 0x310),
+        ("RawValueRangeMax", 0x312),
+        ("RawValueMedian", 0x338),
+        ("RawValueRange", 0x33c),
+    ] {
+        insert(key, r.u16(pos).ok().map(|v| v.to_string()));
+    }
+
+    let capture_time = date_time_original(&r).ok().flatten();
+    insert(
+        "Date/TimeOriginal",
+        capture_time.map(|date_time| date_time.format(DATE_TIME_FORMAT).to_string()),
+    );
+
+    insert("FocusStepCount", r.u16(0x390).ok().map(|v| v.to_string()));
+    insert("FocusDistance", float(0x45c, |d| format!("{:.1} m", d)));
+    insert("FrameRate", r.u16(0x464).ok().map(|v| v.to_string()));
+
+    capture_time
+}
+
+/// The capture time is stored as unix seconds, milliseconds and the time zone offset in minutes
+/// west of UTC.
+fn date_time_original(r: &RecordReader) -> Result<Option<DateTime<FixedOffset>>> {
+    let seconds = r.u32(0x384)? as i64;
+    let millis = (r.u32(0x388)? & 0xffff) as i64;
+    let tz_minutes = r.i16(0x38c)? as i32;
+
+    let Some(offset) = FixedOffset::west_opt(tz_minutes * 60) else {
+        return Ok(None);
+    };
+
+    Ok(DateTime::from_timestamp_millis(seconds * 1000 + millis)
+        .map(|utc| utc.with_timezone(&offset)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::measurement::{evaluate_measurements, Shape};
+    use crate::utils::raw_to_temp;
+
+    const CAMERA_INFO_SIZE: usize = 0x470;
+
+    /// Builds a FFF container with the given records after the header and directory.
+    fn fff(order: ByteOrder, records: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let u32_bytes = |v: u32| match order {
+            ByteOrder::Little => v.to_le_bytes(),
+            ByteOrder::Big => v.to_be_bytes(),
+        };
+        let u16_bytes = |v: u16| match order {
+            ByteOrder::Little => v.to_le_bytes(),
+            ByteOrder::Big => v.to_be_bytes(),
+        };
+
+        let mut data = vec![0; HEADER_SIZE];
+        data[..4].copy_from_slice(b"FFF\0");
+        data[0x04..0x08].copy_from_slice(b"Test");
+        data[0x14..0x18].copy_from_slice(&u32_bytes(100));
+        data[0x18..0x1c].copy_from_slice(&u32_bytes(HEADER_SIZE as u32));
+        data[0x1c..0x20].copy_from_slice(&u32_bytes(records.len() as u32));
+
+        let mut offset = HEADER_SIZE + records.len() * DIRECTORY_ENTRY_SIZE;
+        for (kind, record) in records {
+            let mut entry = [0; DIRECTORY_ENTRY_SIZE];
+            entry[..2].copy_from_slice(&u16_bytes(*kind));
+            entry[0x0c..0x10].copy_from_slice(&u32_bytes(offset as u32));
+            entry[0x10..0x14].copy_from_slice(&u32_bytes(record.len() as u32));
+            data.extend_from_slice(&entry);
+            offset += record.len();
+        }
+        for (_, record) in records {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    /// A RawData record of a 2x1 uncompressed image, in little endian like the cameras write it.
+    fn raw_data() -> Vec<u8> {
+        raw_image(2, 1, &[10000, 20000])
+    }
+
+    /// A RawData record of an uncompressed `width` x `height` image.
+    fn raw_image(width: u16, height: u16, values: &[u16]) -> Vec<u8> {
+        let image: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        raw_record(width, height, &image)
+    }
+
+    /// A RawData record of a `width` x `height` image stored as `image`.
+    fn raw_record(width: u16, height: u16, image: &[u8]) -> Vec<u8> {
+        let mut record = vec![0; RAW_DATA_HEADER_SIZE];
+        record[..2].copy_from_slice(&2u16.to_le_bytes());
+        record[0x02..0x04].copy_from_slice(&width.to_le_bytes());
+        record[0x04..0x06].copy_from_slice(&height.to_le_bytes());
+        record.extend_from_slice(image);
+        record
+    }
+
+    /// A FFF container whose RawData record says the image is `width` x `height` and holds
+    /// `image`, e.g. a PNG file.
+    pub(crate) fn raw_fff(width: u16, height: u16, image: &[u8]) -> Vec<u8> {
+        fff(
+            ByteOrder::Little,
+            &[
+                (RECORD_RAW_DATA, raw_record(width, height, image)),
+                (RECORD_CAMERA_INFO, camera_info()),
+            ],
+        )
+    }
+
+    /// A SEQ frame with a single row of uncompressed raw `values`.
+    pub(crate) fn seq_frame(values: &[u16]) -> Vec<u8> {
+        fff(
+            ByteOrder::Little,
+            &[
+                (RECORD_RAW_DATA, raw_image(values.len() as u16, 1, values)),
+                (RECORD_CAMERA_INFO, camera_info()),
+            ],
+        )
+    }
+
+    /// A CSQ frame with a single row of uncompressed raw `values`.
+    pub(crate) fn csq_frame(values: &[u16]) -> Vec<u8> {
+        let mut data = seq_frame(values);
+        data[0x04..0x08].copy_from_slice(b"RT\0\0");
+        data
+    }
+
+    /// A CameraInfo record with every field the parser reads.
+    fn camera_info() -> Vec<u8> {
+        let mut record = vec![0; CAMERA_INFO_SIZE];
+        let mut set = |pos: usize, bytes: &[u8]| {
+            record[pos..pos + bytes.len()].copy_from_slice(bytes);
+        };
+        set(0x00, &2u16.to_le_bytes());
+        for (pos, value) in [
+            (0x20, 0.9f32),
+            (0x24, 2.0),
+            (0x28, 293.15),
+            (0x2c, 293.15),
+            (0x30, 293.15),
+            (0x34, 1.0),
+            (0x3c, 0.5),
+            (0x58, 16556.0),
+            (0x5c, 1428.0),
+            (0x60, 1.0),
+            (0x70, 0.006569),
+            (0x74, 0.01262),
+            (0x78, -0.002276),
+            (0x7c, -0.00667),
+            (0x80, 1.9),
+            (0x30c, 0.046),
+            (0x45c, 3.5),
+        ] {
+            set(pos, &value.to_le_bytes());
+        }
+        set(0x308, &(-342i32).to_le_bytes());
+        set(0x384, &1_700_000_000u32.to_le_bytes());
+        set(0x464, &30u16.to_le_bytes());
+        record
+    }
+
+    /// A MeasInfo record with a spot at each of `spots`, given as the position and the local
+    /// emissivity, if any.
+    fn meas_info(spots: &[((i16, i16), Option<f32>)]) -> Vec<u8> {
+        let mut record = vec![0; MEAS_INFO_HEADER_SIZE];
+        record[..2].copy_from_slice(&2u16.to_le_bytes());
+        for (i, &((x, y), emissivity)) in spots.iter().enumerate() {
+            let mut tool = vec![0; MEAS_TOOL_HEADER_SIZE];
+            let len = MEAS_TOOL_HEADER_SIZE + 4 + MEAS_LABEL_SIZE;
+            tool[..2].copy_from_slice(&(len as u16).to_le_bytes());
+            tool[0x04..0x06].copy_from_slice(&4u16.to_le_bytes());
+            tool[0x0a..0x0c].copy_from_slice(&1u16.to_le_bytes());
+            if let Some(emissivity) = emissivity {
+                tool[0x0c..0x0e].copy_from_slice(&1u16.to_le_bytes());
+                tool[0x10..0x14].copy_from_slice(&emissivity.to_le_bytes());
+                tool[0x14..0x18].copy_from_slice(&1.0f32.to_le_bytes());
+                tool[0x18..0x1c].copy_from_slice(&293.15f32.to_le_bytes());
+            }
+            tool.extend_from_slice(&x.to_le_bytes());
+            tool.extend_from_slice(&y.to_le_bytes());
+
+            let mut label = [0; MEAS_LABEL_SIZE];
+            for (j, c) in format!("Sp{}", i + 1).encode_utf16().enumerate() {
+                label[j * 2..j * 2 + 2].copy_from_slice(&c.to_le_bytes());
+            }
+            tool.extend_from_slice(&label);
+            record.extend_from_slice(&tool);
+        }
+        record
+    }
+
+    #[test]
+    fn byte_order_is_detected_from_the_version() {
+        for order in [ByteOrder::Little, ByteOrder::Big] {
+            let data = fff(
+                order,
+                &[
+                    (RECORD_RAW_DATA, raw_data()),
+                    (RECORD_CAMERA_INFO, camera_info()),
+                ],
+            );
+
+            let fff = FFFData::parse(&data).unwrap();
+            assert_eq!(fff.raw_thermal_image, [0x10, 0x27, 0x20, 0x4e]);
+            assert_eq!(fff.tags["RawThermalImageWidth"], "2");
+            assert_eq!(fff.tags["RawThermalImageHeight"], "1");
+            assert_eq!(fff.tags["PlanckR1"], "16556");
+            assert_eq!(fff.tags["CreatorSoftware"], "Test");
+        }
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut data = fff(
+            ByteOrder::Little,
+            &[
+                (RECORD_RAW_DATA, raw_data()),
+                (RECORD_CAMERA_INFO, camera_info()),
+            ],
+        );
+        data[0x14..0x18].copy_from_slice(&[0; 4]);
+
+        assert!(FFFData::parse(&data).is_err());
+    }
+
+    #[test]
+    fn records_past_the_end_of_the_data_are_rejected() {
+        let data = fff(
+            ByteOrder::Little,
+            &[
+                (RECORD_RAW_DATA, raw_data()),
+                (RECORD_CAMERA_INFO, camera_info()),
+            ],
+        );
+        let truncated = &data[..data.len() - 1];
+        assert!(FFFData::parse(truncated).is_err());
+
+        // A directory that claims more entries than the data holds.
+        let mut data = fff(ByteOrder::Little, &[(RECORD_RAW_DATA, raw_data())]);
+        data.truncate(HEADER_SIZE + DIRECTORY_ENTRY_SIZE);
+        data[0x1c..0x20].copy_from_slice(&2u32.to_le_bytes());
+        assert!(FFFData::parse(&data).is_err());
+
+        // A directory that starts past the end of the data.
+        let mut data = fff(ByteOrder::Little, &[(RECORD_RAW_DATA, raw_data())]);
+        let len = data.len() as u32;
+        data[0x18..0x1c].copy_from_slice(&(len + 1).to_le_bytes());
+        assert!(FFFData::parse(&data).is_err());
+    }
+
+    #[test]
+    fn missing_raw_data_is_rejected() {
+        let data = fff(ByteOrder::Little, &[(RECORD_CAMERA_INFO, camera_info())]);
+
+        let error = FFFData::parse(&data).unwrap_err();
+        assert!(error.to_string().contains("RawData"));
+    }
+
+    #[test]
+    fn short_camera_info_leaves_out_the_missing_fields() {
+        let mut short = camera_info();
+        short.truncate(0x400);
+        let data = fff(
+            ByteOrder::Little,
+            &[(RECORD_RAW_DATA, raw_data()), (RECORD_CAMERA_INFO, short)],
+        );
+
+        let fff = FFFData::parse(&data).unwrap();
+        assert_eq!(fff.tags["PlanckO"], "-342");
+        assert!(fff.capture_time.is_some());
+        assert!(!fff.tags.contains_key("FocusDistance"));
+        assert!(!fff.tags.contains_key("FrameRate"));
+
+        let metadata = fff.metadata().unwrap();
+        assert_eq!(metadata.focus_distance, None);
+        assert_eq!(metadata.frame_rate, None);
+        assert!(metadata.assumed_values.is_empty());
+    }
+
+    #[test]
+    fn short_camera_info_is_resolved_with_overrides() {
+        let mut short = camera_info();
+        short.truncate(0x300);
+        let data = fff(
+            ByteOrder::Little,
+            &[(RECORD_RAW_DATA, raw_data()), (RECORD_CAMERA_INFO, short)],
+        );
+
+        let fff = FFFData::parse(&data).unwrap();
+        assert!(fff.capture_time.is_none());
+        assert!(matches!(
+            fff.metadata(),
+            Err(Error::MetadataMissing(field)) if field == "PlanckR2"
+        ));
+
+        let overrides = MetadataOverrides {
+            planck_r2: Some(0.05),
+            planck_o: Some(-300.0),
+            ..Default::default()
+        };
+        let metadata = fff.metadata_with_overrides(&overrides).unwrap();
+        assert_eq!(metadata.planck_r2, 0.05);
+        assert_eq!(metadata.planck_o, -300.0);
+        assert_eq!(metadata.planck_r1, 16556.0);
+        assert_eq!(metadata.assumed_values.len(), 2);
+    }
+
+    #[test]
+    fn missing_camera_info_falls_back_to_defaults() {
+        let data = fff(ByteOrder::Little, &[(RECORD_RAW_DATA, raw_data())]);
+
+        let overrides = MetadataOverrides {
+            planck_r1: Some(16556.0),
+            planck_r2: Some(0.046),
+            planck_b: Some(1428.0),
+            planck_f: Some(1.0),
+            planck_o: Some(-342.0),
+            ..Default::default()
+        };
+        let metadata = FFFData::parse(&data)
+            .unwrap()
+            .metadata_with_overrides(&overrides)
+            .unwrap();
+        assert_eq!(metadata.emissivity, 0.95);
+        assert_eq!(metadata.raw_thermal_image_width, Some(2));
+    }
+
+    #[test]
+    fn measurements_use_their_local_emissivity() {
+        let data = fff(
+            ByteOrder::Little,
+            &[
+                (RECORD_RAW_DATA, raw_data()),
+                (RECORD_CAMERA_INFO, camera_info()),
+                (
+                    RECORD_MEAS_INFO,
+                    meas_info(&[((0, 0), None), ((1, 0), Some(0.5))]),
+                ),
+            ],
+        );
+
+        let fff = FFFData::parse(&data).unwrap();
+        let [spot, local] = &fff.measurements[..] else {
+            panic!("expected two measurements, got {:?}", fff.measurements);
+        };
+        assert_eq!(spot.label, "Sp1");
+        assert_eq!(spot.shape, Shape::Spot { x: 0, y: 0 });
+        assert_eq!(spot.local_parameters, None);
+        assert_eq!(local.label, "Sp2");
+        assert_eq!(
+            local.local_parameters,
+            Some(LocalParameters {
+                emissivity: 0.5,
+                object_distance: 1.0,
+                reflected_apparent_temperature: 293.15 - 273.15,
+            })
+        );
+        assert_eq!(fff.tags["Meas2Params"], "1 0");
+
+        let frame = fff.decode_raw_frame(0, 0).unwrap().into_frame().unwrap();
+        let measured = evaluate_measurements(&fff.measurements, &frame).unwrap();
+        assert_eq!(measured[0].unwrap().mean, frame.temperatures[[0, 0]]);
+
+        let mut metadata = (*frame.metadata).clone();
+        metadata.emissivity = 0.5;
+        metadata.object_distance = 1.0;
+        metadata.reflected_apparent_temperature = 293.15 - 273.15;
+        let expected = raw_to_temp(&frame.raw, &metadata).unwrap()[[0, 1]];
+        assert_eq!(measured[1].unwrap().mean, expected);
+        assert_ne!(expected, frame.temperatures[[0, 1]]);
+    }
+
+    #[test]
+    fn embedded_image_is_the_visual_image() {
+        let jpeg = b"\xff\xd8\xff\xe0 not decoded".to_vec();
+        let mut record = vec![0; EMBEDDED_IMAGE_HEADER_SIZE];
+        record[..2].copy_from_slice(&2u16.to_le_bytes());
+        record[0x02..0x04].copy_from_slice(&640u16.to_le_bytes());
+        record[0x04..0x06].copy_from_slice(&480u16.to_le_bytes());
+        record.extend_from_slice(&jpeg);
+        let data = fff(
+            ByteOrder::Little,
+            &[
+                (RECORD_RAW_DATA, raw_data()),
+                (RECORD_EMBEDDED_IMAGE, record),
+            ],
+        );
+
+        let visual = FFFData::parse(&data).unwrap().visual_image().unwrap();
+        assert_eq!((visual.width, visual.height), (640, 480));
+        assert_eq!(visual.image_type, "JPG");
+        assert_eq!(visual.data, jpeg);
+
+        let data = fff(ByteOrder::Little, &[(RECORD_RAW_DATA, raw_data())]);
+        assert_eq!(FFFData::parse(&data).unwrap().visual_image(), None);
+    }
+}
@@ -0,0 +1,385 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use std::io::Write;
+
+use crate::error::Result;
+use crate::measurement::{summarize, MeasuredTemperatures};
+use crate::types::{CSQExifData, Frame};
+
+/// The position of the camera from the GPSInfo record, in signed decimal degrees, negative
+/// for the southern and western hemispheres.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpsInfo {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// The altitude in meters.
+    pub altitude: Option<f64>,
+    /// The direction the camera was pointing at, in degrees clockwise from north.
+    pub img_direction: Option<f64>,
+    pub dilution_of_precision: Option<f64>,
+    pub map_datum: Option<String>,
+}
+
+impl GpsInfo {
+    /// Reads the position from the GPS fields of `metadata`. Returns `None` if the camera had
+    /// no GPS fix.
+    pub fn from_metadata(metadata: &CSQExifData) -> Option<Self> {
+        if metadata.gps_valid == Some(false) {
+            return None;
+        }
+
+        Some(Self {
+            latitude: metadata.gps_latitude?,
+            longitude: metadata.gps_longitude?,
+            altitude: metadata.gps_altitude,
+            img_direction: metadata.gps_img_direction.map(f64::from),
+            dilution_of_precision: metadata.gps_dilution_of_precision.map(f64::from),
+            map_datum: metadata.gps_map_datum.clone(),
+        })
+    }
+}
+
+/// Parses degrees given as decimal degrees or as degrees, minutes and seconds like
+/// `52 deg 31' 12.00" N`. The hemisphere is taken from the value, and from `reference` if
+/// the value has none.
+pub(crate) fn parse_degrees(value: &str, reference: Option<&str>, negative: char) -> Option<f64> {
+    let parts: Vec<f64> = value
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+
+    let magnitude = match parts[..] {
+        [degrees] => degrees,
+        [degrees, minutes] => degrees + minutes / 60.0,
+        [degrees, minutes, seconds] => degrees + minutes / 60.0 + seconds / 3600.0,
+        _ => return None,
+    };
+
+    let hemisphere = value
+        .trim()
+        .chars()
+        .last()
+        .filter(char::is_ascii_alphabetic)
+        .or_else(|| reference?.trim().chars().next());
+    let negative = value.trim_start().starts_with('-') || hemisphere.is_some_and(|h| h == negative);
+
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// A position of a `Track`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    /// The position of the frame within its file.
+    pub index: usize,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub gps: GpsInfo,
+    /// The temperatures of the whole frame.
+    pub temperatures: Option<MeasuredTemperatures>,
+}
+
+/// The path of the camera over a recording, with one point per frame, e.g. to show drone
+/// footage on a map.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub points: Vec<TrackPoint>,
+}
+
+impl Track {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the position of `frame`, with the minimum, maximum and mean temperature of the
+    /// frame. Returns false if the frame has no GPS position.
+    pub fn push(&mut self, frame: &Frame) -> bool {
+        let Some(gps) = GpsInfo::from_metadata(&frame.metadata) else {
+            return false;
+        };
+
+        self.points.push(TrackPoint {
+            index: frame.index,
+            timestamp: frame.timestamp,
+            gps,
+            temperatures: summarize(frame.temperatures.iter().copied()),
+        });
+        true
+    }
+
+    /// Writes the track as GPX 1.1. The temperatures are written as the description of every
+    /// point.
+    pub fn write_gpx<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<gpx version="1.1" creator="csq" xmlns="http://www.topografix.com/GPX/1/1">"#
+        )?;
+        writeln!(writer, "  <trk>")?;
+        writeln!(writer, "    <trkseg>")?;
+
+        for point in &self.points {
+            writeln!(
+                writer,
+                r#"      <trkpt lat="{:.7}" lon="{:.7}">"#,
+                point.gps.latitude, point.gps.longitude
+            )?;
+            if let Some(altitude) = point.gps.altitude {
+                writeln!(writer, "        <ele>{}</ele>", altitude)?;
+            }
+            if let Some(timestamp) = point.timestamp {
+                writeln!(writer, "        <time>{}</time>", format_time(timestamp))?;
+            }
+            writeln!(writer, "        <name>Frame {}</name>", point.index)?;
+            if let Some(temperatures) = point.temperatures {
+                writeln!(writer, "        <desc>{}</desc>", describe(&temperatures))?;
+            }
+            writeln!(writer, "      </trkpt>")?;
+        }
+
+        writeln!(writer, "    </trkseg>")?;
+        writeln!(writer, "  </trk>")?;
+        writeln!(writer, "</gpx>")?;
+        Ok(())
+    }
+
+    /// Writes the track as KML, with a placemark per point and a line along the track.
+    pub fn write_kml<W: Write>(&self, mut writer: W) -> Result<()> {
+        let coordinates = |gps: &GpsInfo| {
+            format!(
+                "{:.7},{:.7},{}",
+                gps.longitude,
+                gps.latitude,
+                gps.altitude.unwrap_or(0.0)
+            )
+        };
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+        writeln!(writer, "  <Document>")?;
+
+        for point in &self.points {
+            writeln!(writer, "    <Placemark>")?;
+            writeln!(writer, "      <name>Frame {}</name>", point.index)?;
+            if let Some(temperatures) = point.temperatures {
+                writeln!(
+                    writer,
+                    "      <description>{}</description>",
+                    describe(&temperatures)
+                )?;
+            }
+            if let Some(timestamp) = point.timestamp {
+                writeln!(
+                    writer,
+                    "      <TimeStamp><when>{}</when></TimeStamp>",
+                    format_time(timestamp)
+                )?;
+            }
+            writeln!(
+                writer,
+                "      <Point><coordinates>{}</coordinates></Point>",
+                coordinates(&point.gps)
+            )?;
+            writeln!(writer, "    </Placemark>")?;
+        }
+
+        writeln!(writer, "    <Placemark>")?;
+        writeln!(writer, "      <name>Track</name>")?;
+        writeln!(writer, "      <LineString>")?;
+        writeln!(writer, "        <altitudeMode>absolute</altitudeMode>")?;
+        writeln!(writer, "        <coordinates>")?;
+        for point in &self.points {
+            writeln!(writer, "          {}", coordinates(&point.gps))?;
+        }
+        writeln!(writer, "        </coordinates>")?;
+        writeln!(writer, "      </LineString>")?;
+        writeln!(writer, "    </Placemark>")?;
+
+        writeln!(writer, "  </Document>")?;
+        writeln!(writer, "</kml>")?;
+        Ok(())
+    }
+}
+
+fn format_time(timestamp: DateTime<FixedOffset>) -> String {
+    timestamp
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn describe(temperatures: &MeasuredTemperatures) -> String {
+    format!(
+        "min {:.2} C, max {:.2} C, mean {:.2} C",
+        temperatures.min, temperatures.max, temperatures.mean
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fff::format_dms;
+    use crate::types::MetadataOverrides;
+    use std::collections::HashMap;
+
+    fn metadata(gps: &[(&str, &str)]) -> CSQExifData {
+        let overrides = MetadataOverrides {
+            planck_r1: Some(16556.0),
+            planck_r2: Some(0.046),
+            planck_b: Some(1428.0),
+            planck_f: Some(1.0),
+            planck_o: Some(-342.0),
+            ..Default::default()
+        };
+        let tags: HashMap<String, String> = gps
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        CSQExifData::from_tags_with_overrides(&tags, &overrides).unwrap()
+    }
+
+    fn point() -> TrackPoint {
+        TrackPoint {
+            index: 3,
+            timestamp: Some(DateTime::parse_from_rfc3339("2024-05-01T12:00:00.25+02:00").unwrap()),
+            gps: GpsInfo {
+                latitude: 52.52,
+                longitude: -13.405,
+                altitude: Some(34.5),
+                img_direction: None,
+                dilution_of_precision: None,
+                map_datum: None,
+            },
+            temperatures: Some(MeasuredTemperatures {
+                min: 10.0,
+                max: 30.5,
+                mean: 20.25,
+                pixels: 4,
+            }),
+        }
+    }
+
+    #[test]
+    fn degrees_are_parsed() {
+        let parse = |value, reference| parse_degrees(value, reference, 'S');
+
+        assert_eq!(parse("52 deg 30' 36.00\" N", None), Some(52.51));
+        assert_eq!(parse("52 deg 30' 36.00\" S", None), Some(-52.51));
+        assert_eq!(parse("52 deg 30' 36.00\"", Some("South")), Some(-52.51));
+        assert_eq!(parse("52 deg 30.6'", Some("N")), Some(52.51));
+        assert_eq!(parse("-52.51", None), Some(-52.51));
+        assert_eq!(parse("52.51", None), Some(52.51));
+        assert_eq!(parse("", None), None);
+        assert_eq!(parse("1 2 3 4", None), None);
+        assert_eq!(parse("52.5.1 N", None), None);
+    }
+
+    #[test]
+    fn formatted_degrees_round_trip() {
+        for value in [0.0, 52.52, -13.405, 89.999_999, -179.5] {
+            let dms = format_dms(value, "E", "W");
+            let parsed = parse_degrees(&dms, None, 'W').unwrap();
+            assert!(
+                (parsed - value).abs() < 1e-5,
+                "{} -> {} -> {}",
+                value,
+                dms,
+                parsed
+            );
+        }
+    }
+
+    #[test]
+    fn southern_and_western_positions_are_negative() {
+        let gps = GpsInfo::from_metadata(&metadata(&[
+            ("GPSLatitude", "33 deg 52' 12.00\" S"),
+            ("GPSLongitude", "151 deg 12' 36.00\""),
+            ("GPSLongitudeRef", "W"),
+            ("GPSAltitude", "12.5 m"),
+            ("GPSMapDatum", "WGS84"),
+        ]))
+        .unwrap();
+
+        assert!((gps.latitude + 33.87).abs() < 1e-9);
+        assert!((gps.longitude + 151.21).abs() < 1e-9);
+        assert_eq!(gps.altitude, Some(12.5));
+        assert_eq!(gps.map_datum.as_deref(), Some("WGS84"));
+
+        let north_east = GpsInfo::from_metadata(&metadata(&[
+            ("GPSLatitude", "33 deg 52' 12.00\""),
+            ("GPSLatitudeRef", "N"),
+            ("GPSLongitude", "151 deg 12' 36.00\" E"),
+        ]))
+        .unwrap();
+        assert!(north_east.latitude > 0.0 && north_east.longitude > 0.0);
+    }
+
+    #[test]
+    fn position_without_fix_is_none() {
+        let metadata = metadata(&[
+            ("GPSLatitude", "33 deg 52' 12.00\" S"),
+            ("GPSLongitude", "151 deg 12' 36.00\" E"),
+            ("GPSValid", "No"),
+        ]);
+        assert_eq!(GpsInfo::from_metadata(&metadata), None);
+        assert_eq!(GpsInfo::from_metadata(&self::metadata(&[])), None);
+    }
+
+    #[test]
+    fn gpx_output() {
+        let track = Track {
+            points: vec![point()],
+        };
+        let mut gpx = vec![];
+        track.write_gpx(&mut gpx).unwrap();
+
+        assert_eq!(
+            String::from_utf8(gpx).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="csq" xmlns="http://www.topografix.com/GPX/1/1">
+  <trk>
+    <trkseg>
+      <trkpt lat="52.5200000" lon="-13.4050000">
+        <ele>34.5</ele>
+        <time>2024-05-01T10:00:00.250Z</time>
+        <name>Frame 3</name>
+        <desc>min 10.00 C, max 30.50 C, mean 20.25 C</desc>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>
+"#
+        );
+    }
+
+    #[test]
+    fn kml_output() {
+        let track = Track {
+            points: vec![point()],
+        };
+        let mut kml = vec![];
+        track.write_kml(&mut kml).unwrap();
+
+        assert_eq!(
+            String::from_utf8(kml).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+  <Document>
+    <Placemark>
+      <name>Frame 3</name>
+      <description>min 10.00 C, max 30.50 C, mean 20.25 C</description>
+      <TimeStamp><when>2024-05-01T10:00:00.250Z</when></TimeStamp>
+      <Point><coordinates>-13.4050000,52.5200000,34.5</coordinates></Point>
+    </Placemark>
+    <Placemark>
+      <name>Track</name>
+      <LineString>
+        <altitudeMode>absolute</altitudeMode>
+        <coordinates>
+          -13.4050000,52.5200000,34.5
+        </coordinates>
+      </LineString>
+    </Placemark>
+  </Document>
+</kml>
+"#
+        );
+    }
+}
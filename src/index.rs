@@ -0,0 +1,192 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::error::Result;
+
+/// Bumped whenever the layout of the index file changes, older index files are rebuilt.
+pub const INDEX_VERSION: u32 = 1;
+
+const INDEX_EXTENSION: &str = "idx";
+
+/// The location and capture time of a single frame within a CSQ file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub length: u64,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+}
+
+/// The sidecar index stored next to a CSQ file, e.g. `recording.csq.idx` for `recording.csq`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameIndex {
+    pub version: u32,
+    pub source_hash: u64,
+    pub frames: Vec<IndexEntry>,
+}
+
+impl FrameIndex {
+    pub fn new(source: &Path, frames: Vec<IndexEntry>) -> Result<Self> {
+        Ok(Self {
+            version: INDEX_VERSION,
+            source_hash: source_hash(source)?,
+            frames,
+        })
+    }
+
+    pub fn sidecar_path(source: &Path) -> PathBuf {
+        let mut file_name = source.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(INDEX_EXTENSION);
+        source.with_file_name(file_name)
+    }
+
+    /// Loads the sidecar index of `source`. Returns `None` if there is none, or if it was
+    /// written by another version or for a different state of the file.
+    pub fn load(source: &Path) -> Result<Option<Self>> {
+        let path = Self::sidecar_path(source);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let index: Self = match serde_json::from_reader(BufReader::new(File::open(path)?)) {
+            Ok(index) => index,
+            Err(_) => return Ok(None),
+        };
+
+        if index.version != INDEX_VERSION || index.source_hash != source_hash(source)? {
+            return Ok(None);
+        }
+
+        Ok(Some(index))
+    }
+
+    pub fn write(&self, source: &Path) -> Result<PathBuf> {
+        let path = Self::sidecar_path(source);
+        serde_json::to_writer(BufWriter::new(File::create(&path)?), self)
+            .map_err(std::io::Error::from)?;
+        Ok(path)
+    }
+}
+
+/// FNV-1a hash of the size and modification time of the file, used to detect stale indexes.
+fn source_hash(source: &Path) -> Result<u64> {
+    let metadata = fs::metadata(source)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let hash = metadata
+        .len()
+        .to_le_bytes()
+        .iter()
+        .chain(modified.to_le_bytes().iter())
+        .fold(0xcbf29ce484222325, |hash: u64, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn source(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("csq-index-{}-{}.csq", name, std::process::id()));
+        fs::write(&path, b"FFF\0RT frames").unwrap();
+        path
+    }
+
+    fn remove(source: &Path) {
+        fs::remove_file(FrameIndex::sidecar_path(source)).unwrap();
+        fs::remove_file(source).unwrap();
+    }
+
+    fn frames() -> Vec<IndexEntry> {
+        let timestamp = DateTime::parse_from_rfc3339("2024-05-01T12:00:00.125+02:00").unwrap();
+        vec![
+            IndexEntry {
+                offset: 0,
+                length: 5_000_000_000,
+                timestamp: Some(timestamp),
+            },
+            IndexEntry {
+                offset: 5_000_000_000,
+                length: 10,
+                timestamp: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn sidecar_round_trip() {
+        let source = source("round-trip");
+        let path = FrameIndex::new(&source, frames())
+            .unwrap()
+            .write(&source)
+            .unwrap();
+        let loaded = FrameIndex::load(&source).unwrap();
+        remove(&source);
+
+        assert_eq!(path.extension().unwrap(), INDEX_EXTENSION);
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.version, INDEX_VERSION);
+        assert_eq!(loaded.frames, frames());
+    }
+
+    #[test]
+    fn index_is_rejected_after_the_source_changed() {
+        let source = source("changed");
+        FrameIndex::new(&source, frames())
+            .unwrap()
+            .write(&source)
+            .unwrap();
+
+        // The same size, but a different modification time.
+        let file = fs::OpenOptions::new().write(true).open(&source).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let modified = FrameIndex::load(&source).unwrap();
+
+        FrameIndex::new(&source, frames())
+            .unwrap()
+            .write(&source)
+            .unwrap();
+        let rewritten = FrameIndex::load(&source).unwrap();
+
+        // The same modification time, but a different size.
+        let mtime = file.metadata().unwrap().modified().unwrap();
+        file.set_len(1).unwrap();
+        file.set_modified(mtime).unwrap();
+        let truncated = FrameIndex::load(&source).unwrap();
+        remove(&source);
+
+        assert!(modified.is_none());
+        assert!(rewritten.is_some());
+        assert!(truncated.is_none());
+    }
+
+    #[test]
+    fn index_of_another_version_is_rejected() {
+        let source = source("version");
+        let mut index = FrameIndex::new(&source, frames()).unwrap();
+        index.version = INDEX_VERSION + 1;
+        index.write(&source).unwrap();
+        let other_version = FrameIndex::load(&source).unwrap();
+
+        fs::write(FrameIndex::sidecar_path(&source), b"not an index").unwrap();
+        let garbage = FrameIndex::load(&source).unwrap();
+        remove(&source);
+
+        assert!(other_version.is_none());
+        assert!(garbage.is_none());
+    }
+}
@@ -0,0 +1,728 @@
+// Decoder for the lossless JPEG-LS (ITU-T T.87) images FLIR uses for the raw thermal data.
+// Only what FLIR cameras produce is supported: a single component with up to 16 bits per sample,
+// optionally with a LSE preset parameters segment. Near-lossless streams are decoded as well.
+use crate::error::{Error, Result};
+use ndarray::Array2;
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOF55: u8 = 0xf7;
+const LSE: u8 = 0xf8;
+const SOS: u8 = 0xda;
+const DRI: u8 = 0xdd;
+
+const BASIC_T1: i32 = 3;
+const BASIC_T2: i32 = 7;
+const BASIC_T3: i32 = 21;
+const DEFAULT_RESET: i32 = 64;
+
+const MIN_C: i32 = -128;
+const MAX_C: i32 = 127;
+
+const REGULAR_CONTEXTS: usize = 365;
+
+const J: [u32; 32] = [
+    0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 9, 10, 11, 12, 13,
+    14, 15,
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FrameHeader {
+    precision: u8,
+    height: usize,
+    width: usize,
+    components: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PresetParameters {
+    max_val: i32,
+    t1: i32,
+    t2: i32,
+    t3: i32,
+    reset: i32,
+}
+
+/// Decodes a JPEG-LS image into an array of `(height, width)` raw sensor values.
+pub fn decode(data: &[u8]) -> Result<Array2<u16>> {
+    let mut pos = 0;
+    let mut frame: Option<FrameHeader> = None;
+    let mut preset = PresetParameters::default();
+
+    if read_marker(data, &mut pos)? != SOI {
+        return Err(Error::DecodeFailed(
+            "JPEG-LS data does not start with SOI marker".into(),
+        ));
+    }
+
+    loop {
+        let marker = read_marker(data, &mut pos)?;
+        if marker == EOI {
+            return Err(Error::DecodeFailed("JPEG-LS data contains no scan".into()));
+        }
+
+        let segment = read_segment(data, &mut pos)?;
+        match marker {
+            SOF55 => frame = Some(parse_frame_header(segment)?),
+            LSE => parse_preset_parameters(segment, &mut preset)?,
+            DRI if segment.len() >= 2 && u16::from_be_bytes([segment[0], segment[1]]) != 0 => {
+                return Err(Error::DecodeFailed(
+                    "JPEG-LS restart intervals are not supported".into(),
+                ));
+            }
+            SOS => {
+                let frame = frame.ok_or_else(|| {
+                    Error::DecodeFailed("JPEG-LS scan before frame header".into())
+                })?;
+                let near = parse_scan_header(segment, &frame)?;
+                let params = CodingParameters::new(&frame, &preset, near)?;
+                let samples = ScanDecoder::new(&data[pos..], frame, params).decode()?;
+
+                return Array2::from_shape_vec((frame.height, frame.width), samples)
+                    .map_err(|e| Error::DecodeFailed(format!("Failed to create ndarray: {e}")));
+            }
+            0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                return Err(Error::DecodeFailed(format!(
+                    "Unsupported JPEG process (SOF marker {:#x}), expected JPEG-LS",
+                    marker
+                )));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_marker(data: &[u8], pos: &mut usize) -> Result<u8> {
+    // Markers may be preceded by any number of fill bytes.
+    while *pos < data.len() && data[*pos] == 0xff {
+        *pos += 1;
+        if *pos < data.len() && data[*pos] != 0xff {
+            let marker = data[*pos];
+            *pos += 1;
+            return Ok(marker);
+        }
+    }
+    Err(Error::DecodeFailed(format!(
+        "Expected JPEG marker at offset {}",
+        *pos
+    )))
+}
+
+fn read_segment<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a [u8]> {
+    let len = data
+        .get(*pos..*pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| Error::DecodeFailed("Truncated JPEG-LS segment".into()))?;
+    if len < 2 {
+        return Err(Error::DecodeFailed("Invalid JPEG-LS segment length".into()));
+    }
+    let segment = data
+        .get(*pos + 2..*pos + len)
+        .ok_or_else(|| Error::DecodeFailed("Truncated JPEG-LS segment".into()))?;
+    *pos += len;
+    Ok(segment)
+}
+
+fn parse_frame_header(segment: &[u8]) -> Result<FrameHeader> {
+    if segment.len() < 6 {
+        return Err(Error::DecodeFailed("Truncated JPEG-LS frame header".into()));
+    }
+
+    let header = FrameHeader {
+        precision: segment[0],
+        height: u16::from_be_bytes([segment[1], segment[2]]) as usize,
+        width: u16::from_be_bytes([segment[3], segment[4]]) as usize,
+        components: segment[5],
+    };
+
+    if !(2..=16).contains(&header.precision) {
+        return Err(Error::DecodeFailed(format!(
+            "Unsupported JPEG-LS precision {}",
+            header.precision
+        )));
+    }
+    if header.components != 1 {
+        return Err(Error::DecodeFailed(format!(
+            "Unsupported JPEG-LS component count {}",
+            header.components
+        )));
+    }
+    if header.width == 0 || header.height == 0 {
+        return Err(Error::DecodeFailed(
+            "JPEG-LS image has no dimensions".into(),
+        ));
+    }
+
+    Ok(header)
+}
+
+fn parse_preset_parameters(segment: &[u8], preset: &mut PresetParameters) -> Result<()> {
+    match segment.first() {
+        Some(1) if segment.len() >= 11 => {
+            let value = |i: usize| u16::from_be_bytes([segment[i], segment[i + 1]]) as i32;
+            *preset = PresetParameters {
+                max_val: value(1),
+                t1: value(3),
+                t2: value(5),
+                t3: value(7),
+                reset: value(9),
+            };
+            Ok(())
+        }
+        Some(id) => Err(Error::DecodeFailed(format!(
+            "Unsupported JPEG-LS LSE segment type {}",
+            id
+        ))),
+        None => Err(Error::DecodeFailed("Empty JPEG-LS LSE segment".into())),
+    }
+}
+
+fn parse_scan_header(segment: &[u8], frame: &FrameHeader) -> Result<i32> {
+    let components = *segment
+        .first()
+        .ok_or_else(|| Error::DecodeFailed("Truncated JPEG-LS scan header".into()))?
+        as usize;
+    if components as u8 != frame.components {
+        return Err(Error::DecodeFailed(
+            "JPEG-LS scan does not cover all components".into(),
+        ));
+    }
+
+    let rest = segment
+        .get(1 + 2 * components..)
+        .filter(|r| r.len() >= 3)
+        .ok_or_else(|| Error::DecodeFailed("Truncated JPEG-LS scan header".into()))?;
+
+    // The mapping table selector of every component has to be 0.
+    if segment[1..1 + 2 * components].chunks(2).any(|c| c[1] != 0) {
+        return Err(Error::DecodeFailed(
+            "JPEG-LS mapping tables are not supported".into(),
+        ));
+    }
+
+    Ok(rest[0] as i32)
+}
+
+/// The derived coding parameters from T.87 annex A.2 and C.2.4.1.
+#[derive(Debug, Clone, Copy)]
+struct CodingParameters {
+    max_val: i32,
+    near: i32,
+    range: i32,
+    qbpp: u32,
+    limit: u32,
+    t1: i32,
+    t2: i32,
+    t3: i32,
+    reset: i32,
+}
+
+impl CodingParameters {
+    fn new(frame: &FrameHeader, preset: &PresetParameters, near: i32) -> Result<Self> {
+        let max_val = if preset.max_val > 0 {
+            preset.max_val
+        } else {
+            (1 << frame.precision) - 1
+        };
+
+        if near < 0 || near > (max_val / 2).min(255) {
+            return Err(Error::DecodeFailed(format!(
+                "Invalid JPEG-LS NEAR value {}",
+                near
+            )));
+        }
+
+        let range = (max_val + 2 * near) / (2 * near + 1) + 1;
+        let qbpp = ceil_log2(range);
+        let bpp = ceil_log2(max_val + 1).max(2);
+        let limit = 2 * (bpp + bpp.max(8));
+
+        let clamp = |i: i32, j: i32| if i > max_val || i < j { j } else { i };
+        let (t1, t2, t3) = if max_val >= 128 {
+            let factor = (max_val.min(4095) + 128) / 256;
+            let t1 = clamp(factor * (BASIC_T1 - 2) + 2 + 3 * near, near + 1);
+            let t2 = clamp(factor * (BASIC_T2 - 3) + 3 + 5 * near, t1);
+            let t3 = clamp(factor * (BASIC_T3 - 4) + 4 + 7 * near, t2);
+            (t1, t2, t3)
+        } else {
+            let factor = 256 / (max_val + 1);
+            let t1 = clamp((BASIC_T1 / factor + 3 * near).max(2), near + 1);
+            let t2 = clamp((BASIC_T2 / factor + 5 * near).max(3), t1);
+            let t3 = clamp((BASIC_T3 / factor + 7 * near).max(4), t2);
+            (t1, t2, t3)
+        };
+
+        let or_default = |value: i32, default: i32| if value > 0 { value } else { default };
+
+        Ok(Self {
+            max_val,
+            near,
+            range,
+            qbpp,
+            limit,
+            t1: or_default(preset.t1, t1),
+            t2: or_default(preset.t2, t2),
+            t3: or_default(preset.t3, t3),
+            reset: or_default(preset.reset, DEFAULT_RESET),
+        })
+    }
+
+    fn quantize_gradient(&self, d: i32) -> i32 {
+        if d <= -self.t3 {
+            -4
+        } else if d <= -self.t2 {
+            -3
+        } else if d <= -self.t1 {
+            -2
+        } else if d < -self.near {
+            -1
+        } else if d <= self.near {
+            0
+        } else if d < self.t1 {
+            1
+        } else if d < self.t2 {
+            2
+        } else if d < self.t3 {
+            3
+        } else {
+            4
+        }
+    }
+
+    fn reconstruct(&self, prediction: i32, error: i32) -> i32 {
+        let mut value = prediction + error * (2 * self.near + 1);
+        if value < -self.near {
+            value += self.range * (2 * self.near + 1);
+        } else if value > self.max_val + self.near {
+            value -= self.range * (2 * self.near + 1);
+        }
+        value.clamp(0, self.max_val)
+    }
+}
+
+fn ceil_log2(value: i32) -> u32 {
+    let mut bits = 0;
+    while (1i64 << bits) < value as i64 {
+        bits += 1;
+    }
+    bits
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RegularContext {
+    a: i32,
+    b: i32,
+    c: i32,
+    n: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RunContext {
+    a: i32,
+    n: i32,
+    nn: i32,
+}
+
+/// Reads the entropy coded segment, removing the stuffed bit after every 0xFF byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    cache: u64,
+    bits: u32,
+    after_ff: bool,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            cache: 0,
+            bits: 0,
+            after_ff: false,
+        }
+    }
+
+    fn fill(&mut self) -> Result<()> {
+        while self.bits <= 56 {
+            let byte = match self.data.get(self.pos) {
+                Some(&b) if !(self.after_ff && b & 0x80 != 0) => b,
+                _ => {
+                    if self.bits == 0 {
+                        return Err(Error::DecodeFailed(
+                            "Unexpected end of JPEG-LS scan data".into(),
+                        ));
+                    }
+                    return Ok(());
+                }
+            };
+            self.pos += 1;
+
+            if self.after_ff {
+                self.cache |= (byte as u64) << (57 - self.bits);
+                self.bits += 7;
+            } else {
+                self.cache |= (byte as u64) << (56 - self.bits);
+                self.bits += 8;
+            }
+            self.after_ff = byte == 0xff;
+        }
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        if self.bits == 0 {
+            self.fill()?;
+        }
+        let bit = self.cache >> 63 != 0;
+        self.cache <<= 1;
+        self.bits -= 1;
+        Ok(bit)
+    }
+
+    fn read_bits(&mut self, count: u32) -> Result<i32> {
+        let mut value = 0;
+        let mut remaining = count;
+        while remaining > 0 {
+            if self.bits == 0 {
+                self.fill()?;
+            }
+            let take = remaining.min(self.bits);
+            value = (value << take) | (self.cache >> (64 - take)) as i32;
+            self.cache <<= take;
+            self.bits -= take;
+            remaining -= take;
+        }
+        Ok(value)
+    }
+
+    fn read_unary(&mut self, max: u32) -> Result<u32> {
+        let mut zeros = 0;
+        while !self.read_bit()? {
+            zeros += 1;
+            if zeros > max {
+                return Err(Error::DecodeFailed(
+                    "Invalid Golomb code in JPEG-LS scan".into(),
+                ));
+            }
+        }
+        Ok(zeros)
+    }
+}
+
+struct ScanDecoder<'a> {
+    bits: BitReader<'a>,
+    frame: FrameHeader,
+    params: CodingParameters,
+    contexts: [RegularContext; REGULAR_CONTEXTS],
+    run_contexts: [RunContext; 2],
+    run_index: usize,
+}
+
+impl<'a> ScanDecoder<'a> {
+    fn new(data: &'a [u8], frame: FrameHeader, params: CodingParameters) -> Self {
+        let a = ((params.range + 32) / 64).max(2);
+        Self {
+            bits: BitReader::new(data),
+            frame,
+            params,
+            contexts: [RegularContext {
+                a,
+                b: 0,
+                c: 0,
+                n: 1,
+            }; REGULAR_CONTEXTS],
+            run_contexts: [RunContext { a, n: 1, nn: 0 }; 2],
+            run_index: 0,
+        }
+    }
+
+    fn decode(mut self) -> Result<Vec<u16>> {
+        let width = self.frame.width;
+        let mut samples = Vec::with_capacity(width * self.frame.height);
+
+        // Both lines carry one extra sample on each side, so index 1 is the first sample.
+        let mut previous = vec![0i32; width + 2];
+        let mut current = vec![0i32; width + 2];
+
+        for _ in 0..self.frame.height {
+            current[0] = previous[1];
+            previous[width + 1] = previous[width];
+
+            self.decode_line(&previous, &mut current)?;
+
+            samples.extend(current[1..=width].iter().map(|&v| v as u16));
+            std::mem::swap(&mut previous, &mut current);
+        }
+
+        Ok(samples)
+    }
+
+    fn decode_line(&mut self, previous: &[i32], current: &mut [i32]) -> Result<()> {
+        let width = self.frame.width;
+        let mut x = 1;
+
+        while x <= width {
+            let ra = current[x - 1];
+            let rb = previous[x];
+            let rc = previous[x - 1];
+            let rd = previous[x + 1];
+
+            let q1 = self.params.quantize_gradient(rd - rb);
+            let q2 = self.params.quantize_gradient(rb - rc);
+            let q3 = self.params.quantize_gradient(rc - ra);
+
+            if q1 == 0 && q2 == 0 && q3 == 0 {
+                x += self.decode_run(ra, previous, current, x)?;
+            } else {
+                current[x] = self.decode_regular(q1, q2, q3, ra, rb, rc)?;
+                x += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_value(&mut self, k: u32, limit: u32) -> Result<i32> {
+        let qbpp = self.params.qbpp;
+        let escape = limit - qbpp - 1;
+        let high = self.bits.read_unary(escape)?;
+        if high >= escape {
+            return Ok(self.bits.read_bits(qbpp)? + 1);
+        }
+        Ok(((high as i32) << k) | self.bits.read_bits(k)?)
+    }
+
+    fn decode_regular(
+        &mut self,
+        q1: i32,
+        q2: i32,
+        q3: i32,
+        ra: i32,
+        rb: i32,
+        rc: i32,
+    ) -> Result<i32> {
+        let first = if q1 != 0 {
+            q1
+        } else if q2 != 0 {
+            q2
+        } else {
+            q3
+        };
+        let sign = if first < 0 { -1 } else { 1 };
+        let q = ((q1 * 81 + q2 * 9 + q3) * sign) as usize;
+
+        let prediction = if rc >= ra.max(rb) {
+            ra.min(rb)
+        } else if rc <= ra.min(rb) {
+            ra.max(rb)
+        } else {
+            ra + rb - rc
+        };
+
+        let ctx = self.contexts[q];
+        let prediction = (prediction + sign * ctx.c).clamp(0, self.params.max_val);
+
+        let mut k = 0;
+        while (ctx.n << k) < ctx.a {
+            k += 1;
+        }
+
+        let mapped = self.decode_value(k, self.params.limit)?;
+        let mut error = if mapped & 1 == 0 {
+            mapped >> 1
+        } else {
+            -((mapped + 1) >> 1)
+        };
+        if k == 0 && self.params.near == 0 && 2 * ctx.b <= -ctx.n {
+            error = -error - 1;
+        }
+
+        self.update_regular(q, error);
+
+        Ok(self.params.reconstruct(prediction, sign * error))
+    }
+
+    fn update_regular(&mut self, q: usize, error: i32) {
+        let reset = self.params.reset;
+        let near = self.params.near;
+        let ctx = &mut self.contexts[q];
+
+        ctx.b += error * (2 * near + 1);
+        ctx.a += error.abs();
+        if ctx.n == reset {
+            ctx.a >>= 1;
+            ctx.b >>= 1;
+            ctx.n >>= 1;
+        }
+        ctx.n += 1;
+
+        if ctx.b <= -ctx.n {
+            ctx.b += ctx.n;
+            if ctx.c > MIN_C {
+                ctx.c -= 1;
+            }
+            if ctx.b <= -ctx.n {
+                ctx.b = -ctx.n + 1;
+            }
+        } else if ctx.b > 0 {
+            ctx.b -= ctx.n;
+            if ctx.c < MAX_C {
+                ctx.c += 1;
+            }
+            if ctx.b > 0 {
+                ctx.b = 0;
+            }
+        }
+    }
+
+    /// Decodes a run starting at `x` including the interruption sample, returning the number of
+    /// samples written.
+    fn decode_run(
+        &mut self,
+        ra: i32,
+        previous: &[i32],
+        current: &mut [i32],
+        x: usize,
+    ) -> Result<usize> {
+        let remaining = self.frame.width + 1 - x;
+        let mut length = 0;
+
+        while self.bits.read_bit()? {
+            let count = (1 << J[self.run_index]).min(remaining - length);
+            length += count;
+            if count == 1 << J[self.run_index] {
+                self.run_index = (self.run_index + 1).min(31);
+            }
+            if length == remaining {
+                break;
+            }
+        }
+
+        if length != remaining {
+            length += self.bits.read_bits(J[self.run_index])? as usize;
+            if length >= remaining {
+                return Err(Error::DecodeFailed(
+                    "JPEG-LS run exceeds line length".into(),
+                ));
+            }
+        }
+
+        current[x..x + length].fill(ra);
+
+        if length == remaining {
+            return Ok(length);
+        }
+
+        let end = x + length;
+        let rb = previous[end];
+        current[end] = self.decode_run_interruption(ra, rb)?;
+        self.run_index = self.run_index.saturating_sub(1);
+
+        Ok(length + 1)
+    }
+
+    fn decode_run_interruption(&mut self, ra: i32, rb: i32) -> Result<i32> {
+        let ri_type = ((ra - rb).abs() <= self.params.near) as i32;
+        let ctx = self.run_contexts[ri_type as usize];
+
+        let temp = ctx.a + (ctx.n >> 1) * ri_type;
+        let mut k = 0;
+        while (ctx.n << k) < temp {
+            k += 1;
+        }
+
+        let limit = self.params.limit - J[self.run_index] - 1;
+        let em_error = self.decode_value(k, limit)?;
+
+        let temp = em_error + ri_type;
+        let map = temp & 1;
+        let abs_error = (temp + map) / 2;
+        let error = if (k != 0 || 2 * ctx.nn >= ctx.n) == (map == 1) {
+            -abs_error
+        } else {
+            abs_error
+        };
+
+        let ctx = &mut self.run_contexts[ri_type as usize];
+        if error < 0 {
+            ctx.nn += 1;
+        }
+        ctx.a += (em_error + 1 - ri_type) >> 1;
+        if ctx.n == self.params.reset {
+            ctx.a >>= 1;
+            ctx.n >>= 1;
+            ctx.nn >>= 1;
+        }
+        ctx.n += 1;
+
+        if ri_type == 1 {
+            Ok(self.params.reconstruct(ra, error))
+        } else {
+            let sign = if rb > ra { 1 } else { -1 };
+            Ok(self.params.reconstruct(rb, sign * error))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    /// The example of ITU-T T.87 Annex H.3, an 8 bit image with a run at the end of the last
+    /// two lines, and the bitstream the standard gives for it.
+    const ANNEX_H3: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xf7, 0x00, 0x0b, 0x08, 0x00, 0x04, 0x00, 0x04, 0x01, 0x01, 0x11, 0x00,
+        0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x6c, 0x80,
+        0x20, 0x8e, 0x01, 0xc0, 0x00, 0x00, 0x57, 0x40, 0x00, 0x00, 0x6e, 0xe6, 0x00, 0x00, 0x01,
+        0xbc, 0x18, 0x00, 0x00, 0x05, 0xd8, 0x00, 0x00, 0x91, 0x60, 0xff, 0xd9,
+    ];
+
+    /// A 16 bit lossless image with runs, encoded following T.87 with default parameters. The
+    /// entropy coded data contains a 0xFF byte, so the next byte has a stuffed zero bit.
+    const STUFFED_16_BIT: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xf7, 0x00, 0x0b, 0x10, 0x00, 0x04, 0x00, 0x06, 0x01, 0x01, 0x11, 0x00,
+        0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0xea, 0x5e, 0x80, 0x10, 0x04, 0x01, 0x00, 0x80, 0x40, 0x03, 0xae, 0xd8, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x0b, 0xae, 0xdf, 0x80, 0x00, 0x00, 0x00, 0x00, 0xbb, 0x90, 0x04, 0x10,
+        0x24, 0x40, 0x17, 0x18, 0x89, 0x44, 0xf1, 0xff, 0x38, 0x11, 0x74, 0x6f, 0x4c, 0x00, 0x00,
+        0x1b, 0xc0, 0xff, 0xd9,
+    ];
+
+    #[test]
+    fn decodes_annex_h3() {
+        assert_eq!(
+            decode(ANNEX_H3).unwrap(),
+            array![
+                [0, 0, 90, 74],
+                [68, 50, 43, 205],
+                [64, 145, 145, 145],
+                [100, 145, 145, 145],
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_16_bit_with_bit_stuffing() {
+        let scan = &STUFFED_16_BIT[25..STUFFED_16_BIT.len() - 2];
+        assert!(scan.windows(2).any(|w| w[0] == 0xff && w[1] < 0x80));
+
+        assert_eq!(
+            decode(STUFFED_16_BIT).unwrap(),
+            array![
+                [30000, 30000, 30000, 30000, 30000, 30000],
+                [30000, 45086, 30000, 30000, 30000, 30000],
+                [30000, 30000, 30000, 30000, 54649, 35308],
+                [30000, 48984, 32607, 30000, 39333, 30000],
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let truncated = &STUFFED_16_BIT[..STUFFED_16_BIT.len() / 2];
+        assert!(decode(truncated).is_err());
+        assert!(decode(&ANNEX_H3[..10]).is_err());
+    }
+}
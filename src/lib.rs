@@ -1,5 +1,37 @@
+mod align;
 mod csq;
+mod error;
+mod fff;
+mod gps;
+mod index;
+mod jpegls;
+mod measurement;
+mod palette;
+mod parallel;
+mod raw;
+mod rjpeg;
+mod splitter;
+mod timeline;
 mod types;
 mod utils;
+mod visual;
 
+pub use align::Alignment;
 pub use csq::CSQReader;
+pub use error::{Error, Result};
+pub use fff::{decode_fff, read_fff, FFFData};
+pub use gps::{GpsInfo, Track, TrackPoint};
+pub use measurement::{
+    evaluate_measurements, LocalParameters, MeasuredTemperatures, Measurement, Shape,
+};
+pub use palette::{Palette, Renderer};
+pub use parallel::ParallelFrames;
+pub use rjpeg::{decode_radiometric_jpeg, extract_fff, read_radiometric_jpeg};
+pub use splitter::FileFormat;
+pub use timeline::{FrameGap, FrameRateReport, Timeline};
+pub use types::{
+    AssumedValue, CSQExifData, DecodedFrame, Frame, MetadataOverrides, RawFrame, RecoveryPolicy,
+    SkippedFrame, ValueSource,
+};
+pub use utils::raw_to_temp;
+pub use visual::VisualImage;
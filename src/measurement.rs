@@ -0,0 +1,352 @@
+use ndarray::Array2;
+
+use crate::error::{Error, Result};
+use crate::types::Frame;
+use crate::utils::raw_to_temp;
+
+/// A measurement tool the operator placed on the image, from the MeasInfo record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// The label shown by the camera, e.g. `Sp1` or `Bx1`.
+    pub label: String,
+    /// The tool type, named like exiftool's `MeasNType`, e.g. `Spot` or `Area`.
+    pub tool_type: String,
+    pub shape: Shape,
+    /// The coordinates of the tool as stored by the camera, in thermal pixels.
+    pub params: Vec<i16>,
+    /// The object parameters of the tool, if the operator set them for it instead of using
+    /// those of the image.
+    pub local_parameters: Option<LocalParameters>,
+}
+
+/// The region of the thermal image a measurement tool covers, in thermal pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Spot {
+        x: i16,
+        y: i16,
+    },
+    /// A box with its top left corner at (`x`, `y`).
+    Area {
+        x: i16,
+        y: i16,
+        width: i16,
+        height: i16,
+    },
+    /// An ellipse around (`x`, `y`).
+    Ellipse {
+        x: i16,
+        y: i16,
+        radius_x: i16,
+        radius_y: i16,
+    },
+    Line {
+        x1: i16,
+        y1: i16,
+        x2: i16,
+        y2: i16,
+    },
+    /// Tools that do not cover a region of the image, like alarms and differences.
+    Other,
+}
+
+/// Object parameters that replace those of the image within a measurement tool.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalParameters {
+    pub emissivity: f32,
+    /// The distance to the object in meters.
+    pub object_distance: f32,
+    /// The reflected apparent temperature in degrees Celsius.
+    pub reflected_apparent_temperature: f32,
+}
+
+/// The temperatures within a measurement tool, in degrees Celsius.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasuredTemperatures {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// The number of pixels the temperatures were taken from.
+    pub pixels: usize,
+}
+
+impl Measurement {
+    /// Measures the temperatures within the tool on `frame`, like the camera did, with the
+    /// local object parameters of the tool if it has any. Returns `None` for tools that do not
+    /// cover a region of the image, tools outside of the image and placeholder frames. Fails if
+    /// the raw values and temperatures of `frame` differ in shape.
+    pub fn evaluate(&self, frame: &Frame) -> Result<Option<MeasuredTemperatures>> {
+        if frame.placeholder {
+            return Ok(None);
+        }
+
+        if frame.raw.dim() != frame.temperatures.dim() {
+            return Err(Error::InvalidFrame(format!(
+                "Raw values of shape {:?} do not match temperatures of shape {:?}",
+                frame.raw.dim(),
+                frame.temperatures.dim()
+            )));
+        }
+
+        let pixels = self.pixels(frame.temperatures.dim());
+        let temperatures: Vec<f32> = match self.local_parameters {
+            Some(local) => {
+                let mut metadata = (*frame.metadata).clone();
+                metadata.emissivity = local.emissivity;
+                metadata.object_distance = local.object_distance;
+                metadata.reflected_apparent_temperature = local.reflected_apparent_temperature;
+
+                // Only the pixels of the tool are converted again.
+                let raw = Array2::from_shape_fn((1, pixels.len()), |(_, i)| frame.raw[pixels[i]]);
+                raw_to_temp(&raw, &metadata)?.into_iter().collect()
+            }
+            None => pixels.iter().map(|&p| frame.temperatures[p]).collect(),
+        };
+
+        Ok(summarize(temperatures.into_iter()))
+    }
+
+    /// The pixels covered by the tool within an image of `size` (height, width), as (y, x).
+    pub fn pixels(&self, (height, width): (usize, usize)) -> Vec<(usize, usize)> {
+        let (width, height) = (width as i32, height as i32);
+        let inside = |x: i32, y: i32| {
+            (x >= 0 && y >= 0 && x < width && y < height).then_some((y as usize, x as usize))
+        };
+        // The range of `start..end` within `0..len`, so tools reaching far outside of the image
+        // do not iterate over pixels that are left out anyway.
+        let clamp = |start: i32, end: i32, len: i32| start.clamp(0, len)..end.clamp(0, len);
+
+        match self.shape {
+            Shape::Spot { x, y } => inside(x.into(), y.into()).into_iter().collect(),
+            Shape::Area {
+                x,
+                y,
+                width: w,
+                height: h,
+            } => {
+                let (x, y) = (i32::from(x), i32::from(y));
+                let xs = clamp(x, x + i32::from(w), width);
+                clamp(y, y + i32::from(h), height)
+                    .flat_map(|py| xs.clone().map(move |px| (py as usize, px as usize)))
+                    .collect()
+            }
+            Shape::Ellipse {
+                x,
+                y,
+                radius_x,
+                radius_y,
+            } => {
+                let (x, y) = (i32::from(x), i32::from(y));
+                let (rx, ry) = (i32::from(radius_x).abs(), i32::from(radius_y).abs());
+                let xs = clamp(x - rx, x + rx + 1, width);
+                clamp(y - ry, y + ry + 1, height)
+                    .flat_map(|py| xs.clone().map(move |px| (px, py)))
+                    .filter(|&(px, py)| {
+                        let dx = (px - x) as f32 / rx.max(1) as f32;
+                        let dy = (py - y) as f32 / ry.max(1) as f32;
+                        dx * dx + dy * dy <= 1.0
+                    })
+                    .map(|(px, py)| (py as usize, px as usize))
+                    .collect()
+            }
+            Shape::Line { x1, y1, x2, y2 } => line(x1.into(), y1.into(), x2.into(), y2.into())
+                .into_iter()
+                .filter_map(|(px, py)| inside(px, py))
+                .collect(),
+            Shape::Other => vec![],
+        }
+    }
+}
+
+/// The name exiftool gives the tool type of a MeasInfo record.
+pub(crate) fn tool_type_name(tool_type: u16) -> String {
+    match tool_type {
+        1 => "Spot".into(),
+        2 => "Area".into(),
+        3 => "Ellipse".into(),
+        4 => "Line".into(),
+        5 => "Endpoint".into(),
+        6 => "Alarm".into(),
+        7 => "Unused".into(),
+        8 => "Difference".into(),
+        other => format!("Unknown ({})", other),
+    }
+}
+
+/// The shape of a tool from its type and coordinates, which are X,Y for spots, X1,Y1,W,H for
+/// areas, XC,YC,X1,Y1,X2,Y2 for ellipses and X1,Y1,X2,Y2 for lines.
+pub(crate) fn shape(tool_type: u16, params: &[i16]) -> Shape {
+    match (tool_type, params) {
+        (1, &[x, y, ..]) => Shape::Spot { x, y },
+        (2, &[x, y, width, height, ..]) => Shape::Area {
+            x,
+            y,
+            width,
+            height,
+        },
+        (3, &[x, y, x1, y1, x2, y2, ..]) => Shape::Ellipse {
+            x,
+            y,
+            radius_x: radius(x, x1, x2),
+            radius_y: radius(y, y1, y2),
+        },
+        (4, &[x1, y1, x2, y2, ..]) => Shape::Line { x1, y1, x2, y2 },
+        _ => Shape::Other,
+    }
+}
+
+fn radius(center: i16, a: i16, b: i16) -> i16 {
+    center
+        .abs_diff(a)
+        .max(center.abs_diff(b))
+        .min(i16::MAX as u16) as i16
+}
+
+/// The pixels from (`x1`, `y1`) to (`x2`, `y2`), with Bresenham's algorithm.
+fn line(x1: i32, y1: i32, x2: i32, y2: i32) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+
+    let mut points = vec![(x, y)];
+    while (x, y) != (x2, y2) {
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+        points.push((x, y));
+    }
+    points
+}
+
+/// The minimum, maximum and mean of `temperatures`, leaving out NaN values.
+pub(crate) fn summarize(temperatures: impl Iterator<Item = f32>) -> Option<MeasuredTemperatures> {
+    let (min, max, sum, pixels) = temperatures.filter(|t| !t.is_nan()).fold(
+        (f32::INFINITY, f32::NEG_INFINITY, 0.0f64, 0),
+        |(min, max, sum, pixels), t| (min.min(t), max.max(t), sum + t as f64, pixels + 1),
+    );
+
+    (pixels > 0).then(|| MeasuredTemperatures {
+        min,
+        max,
+        mean: (sum / pixels as f64) as f32,
+        pixels,
+    })
+}
+
+/// Measures every tool of `measurements` on `frame`, see `Measurement::evaluate`.
+pub fn evaluate_measurements(
+    measurements: &[Measurement],
+    frame: &Frame,
+) -> Result<Vec<Option<MeasuredTemperatures>>> {
+    measurements.iter().map(|m| m.evaluate(frame)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(shape: Shape) -> Measurement {
+        Measurement {
+            label: String::new(),
+            tool_type: String::new(),
+            shape,
+            params: vec![],
+            local_parameters: None,
+        }
+    }
+
+    #[test]
+    fn spot_covers_one_pixel_inside_the_image() {
+        assert_eq!(tool(Shape::Spot { x: 2, y: 1 }).pixels((3, 4)), [(1, 2)]);
+        assert!(tool(Shape::Spot { x: 4, y: 1 }).pixels((3, 4)).is_empty());
+        assert!(tool(Shape::Spot { x: -1, y: 0 }).pixels((3, 4)).is_empty());
+    }
+
+    #[test]
+    fn area_is_clipped_to_the_image() {
+        let area = tool(Shape::Area {
+            x: 2,
+            y: -1,
+            width: 10,
+            height: 2,
+        });
+        assert_eq!(area.pixels((3, 4)), [(0, 2), (0, 3)]);
+
+        let huge = tool(Shape::Area {
+            x: i16::MIN,
+            y: i16::MIN,
+            width: i16::MAX,
+            height: i16::MAX,
+        });
+        assert!(huge.pixels((3, 4)).is_empty());
+
+        let negative = tool(Shape::Area {
+            x: 1,
+            y: 1,
+            width: -1,
+            height: 1,
+        });
+        assert!(negative.pixels((3, 4)).is_empty());
+    }
+
+    #[test]
+    fn ellipse_covers_the_pixels_within_its_radii() {
+        let ellipse = tool(Shape::Ellipse {
+            x: 0,
+            y: 1,
+            radius_x: 1,
+            radius_y: 1,
+        });
+        assert_eq!(ellipse.pixels((3, 4)), [(0, 0), (1, 0), (1, 1), (2, 0)]);
+
+        let huge = tool(Shape::Ellipse {
+            x: 1,
+            y: 1,
+            radius_x: i16::MAX,
+            radius_y: i16::MAX,
+        });
+        assert_eq!(huge.pixels((3, 4)).len(), 12);
+    }
+
+    #[test]
+    fn line_is_clipped_at_the_image_edges() {
+        let line = tool(Shape::Line {
+            x1: -1,
+            y1: -1,
+            x2: 4,
+            y2: 4,
+        });
+        assert_eq!(line.pixels((3, 4)), [(0, 0), (1, 1), (2, 2)]);
+
+        let reversed = tool(Shape::Line {
+            x1: 3,
+            y1: 0,
+            x2: 0,
+            y2: 0,
+        });
+        assert_eq!(reversed.pixels((1, 4)), [(0, 3), (0, 2), (0, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn summarize_leaves_out_nan() {
+        let summary = summarize([1.0, f32::NAN, 3.0, 5.0].into_iter()).unwrap();
+        assert_eq!(
+            summary,
+            MeasuredTemperatures {
+                min: 1.0,
+                max: 5.0,
+                mean: 3.0,
+                pixels: 3,
+            }
+        );
+
+        assert_eq!(summarize([f32::NAN].into_iter()), None);
+        assert_eq!(summarize(std::iter::empty()), None);
+    }
+}
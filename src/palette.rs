@@ -0,0 +1,276 @@
+use ndarray::{Array2, Array3};
+
+use crate::types::Frame;
+
+/// The color of pixels that are NaN, e.g. in placeholder frames.
+const MISSING_COLOR: [u8; 3] = [0, 0, 0];
+
+/// The color palette the camera displayed the thermal image with, from the PaletteInfo record.
+///
+/// FLIR stores the colors as YCrCb, they are converted to RGB when the record is read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    /// The name shown by the camera, e.g. `Iron`.
+    pub name: String,
+    /// The colors from the coldest to the hottest temperature of the displayed range.
+    pub colors: Vec<[u8; 3]>,
+    /// The color of temperatures above the displayed range.
+    pub above_color: [u8; 3],
+    /// The color of temperatures below the displayed range.
+    pub below_color: [u8; 3],
+    /// The color of temperatures above the measurement range of the camera.
+    pub overflow_color: [u8; 3],
+    /// The color of temperatures below the measurement range of the camera.
+    pub underflow_color: [u8; 3],
+    /// The color of temperatures within the first isotherm.
+    pub isotherm1_color: [u8; 3],
+    /// The color of temperatures within the second isotherm.
+    pub isotherm2_color: [u8; 3],
+}
+
+impl Palette {
+    /// A palette from black to white, for files without a PaletteInfo record.
+    pub fn grayscale() -> Self {
+        Self {
+            name: "Grayscale".into(),
+            colors: (0..=255).map(|l| [l, l, l]).collect(),
+            above_color: [255, 255, 255],
+            below_color: [0, 0, 0],
+            overflow_color: [255, 0, 0],
+            underflow_color: [0, 0, 255],
+            isotherm1_color: [255, 255, 0],
+            isotherm2_color: [0, 255, 0],
+        }
+    }
+
+    /// The color at `fraction` of the palette, between 0 for the first and 1 for the last color.
+    pub fn color_at(&self, fraction: f32) -> [u8; 3] {
+        let Some(last) = self.colors.len().checked_sub(1) else {
+            return MISSING_COLOR;
+        };
+        let index = (fraction.clamp(0.0, 1.0) * last as f32).round() as usize;
+        self.colors[index]
+    }
+}
+
+/// Converts a color of the PaletteInfo record, stored as Y, Cr and Cb, to RGB.
+pub(crate) fn ycrcb_to_rgb([y, cr, cb]: [u8; 3]) -> [u8; 3] {
+    let (y, cr, cb) = (y as f32, cr as f32 - 128.0, cb as f32 - 128.0);
+    [
+        y + 1.402 * cr,
+        y - 0.344_136 * cb - 0.714_136 * cr,
+        y + 1.772 * cb,
+    ]
+    .map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Draws temperatures with a `Palette`, the way the camera displayed them.
+///
+/// Temperatures within the displayed range take the colors of the palette, temperatures
+/// outside of it the above and below colors, and temperatures outside of the measurement range
+/// of the camera the overflow and underflow colors. The isotherms paint temperatures within
+/// them in the isotherm colors, the first isotherm wins where they overlap.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    palette: Palette,
+    range: Option<(f32, f32)>,
+    camera_range: Option<(f32, f32)>,
+    isotherm1: Option<(f32, f32)>,
+    isotherm2: Option<(f32, f32)>,
+}
+
+impl Renderer {
+    pub fn new(palette: Palette) -> Self {
+        Self {
+            palette,
+            range: None,
+            camera_range: None,
+            isotherm1: None,
+            isotherm2: None,
+        }
+    }
+
+    /// Sets the displayed range in degrees Celsius. Without it, every image is stretched from
+    /// its coldest to its hottest temperature.
+    pub fn range(mut self, min: f32, max: f32) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Sets the measurement range of the camera in degrees Celsius. `render_frame` takes it
+    /// from the metadata of the frame if it is not set.
+    pub fn camera_range(mut self, min: f32, max: f32) -> Self {
+        self.camera_range = Some((min, max));
+        self
+    }
+
+    /// Paints temperatures from `min` to `max` degrees Celsius in the first isotherm color.
+    pub fn isotherm1(mut self, min: f32, max: f32) -> Self {
+        self.isotherm1 = Some((min, max));
+        self
+    }
+
+    /// Paints temperatures from `min` to `max` degrees Celsius in the second isotherm color.
+    pub fn isotherm2(mut self, min: f32, max: f32) -> Self {
+        self.isotherm2 = Some((min, max));
+        self
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Draws `temperatures` into an array of shape (height, width, 3) of RGB values.
+    pub fn render(&self, temperatures: &Array2<f32>) -> Array3<u8> {
+        self.render_with_camera_range(temperatures, self.camera_range)
+    }
+
+    /// Like `render`, with the measurement range of the camera from the metadata of `frame`.
+    pub fn render_frame(&self, frame: &Frame) -> Array3<u8> {
+        let camera_range = self
+            .camera_range
+            .or_else(|| frame.metadata.camera_temperature_range());
+        self.render_with_camera_range(&frame.temperatures, camera_range)
+    }
+
+    fn render_with_camera_range(
+        &self,
+        temperatures: &Array2<f32>,
+        camera_range: Option<(f32, f32)>,
+    ) -> Array3<u8> {
+        let (min, max) = self.range.unwrap_or_else(|| {
+            temperatures
+                .iter()
+                .filter(|t| !t.is_nan())
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &t| {
+                    (min.min(t), max.max(t))
+                })
+        });
+        let within =
+            |range: Option<(f32, f32)>, t: f32| range.is_some_and(|(a, b)| t >= a && t <= b);
+        let palette = &self.palette;
+
+        let color = |t: f32| -> [u8; 3] {
+            if t.is_nan() {
+                return MISSING_COLOR;
+            }
+            if let Some((camera_min, camera_max)) = camera_range {
+                if t > camera_max {
+                    return palette.overflow_color;
+                }
+                if t < camera_min {
+                    return palette.underflow_color;
+                }
+            }
+            if within(self.isotherm1, t) {
+                return palette.isotherm1_color;
+            }
+            if within(self.isotherm2, t) {
+                return palette.isotherm2_color;
+            }
+            if t > max {
+                return palette.above_color;
+            }
+            if t < min {
+                return palette.below_color;
+            }
+            if max > min {
+                palette.color_at((t - min) / (max - min))
+            } else {
+                palette.color_at(0.0)
+            }
+        };
+
+        let (height, width) = temperatures.dim();
+        let mut image = Array3::zeros((height, width, 3));
+        for ((y, x), &t) in temperatures.indexed_iter() {
+            let [r, g, b] = color(t);
+            image[[y, x, 0]] = r;
+            image[[y, x, 1]] = g;
+            image[[y, x, 2]] = b;
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr2;
+
+    fn palette() -> Palette {
+        Palette {
+            name: "Test".into(),
+            colors: vec![[0, 0, 0], [128, 128, 128], [255, 255, 255]],
+            above_color: [1, 0, 0],
+            below_color: [2, 0, 0],
+            overflow_color: [3, 0, 0],
+            underflow_color: [4, 0, 0],
+            isotherm1_color: [5, 0, 0],
+            isotherm2_color: [6, 0, 0],
+        }
+    }
+
+    fn colors(image: &Array3<u8>) -> Vec<[u8; 3]> {
+        image
+            .outer_iter()
+            .flat_map(|row| {
+                row.outer_iter()
+                    .map(|c| [c[0], c[1], c[2]])
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ycrcb_is_converted_to_rgb() {
+        assert_eq!(ycrcb_to_rgb([0, 128, 128]), [0, 0, 0]);
+        assert_eq!(ycrcb_to_rgb([255, 128, 128]), [255, 255, 255]);
+        assert_eq!(ycrcb_to_rgb([76, 255, 85]), [254, 0, 0]);
+        assert_eq!(ycrcb_to_rgb([29, 107, 255]), [0, 0, 254]);
+    }
+
+    #[test]
+    fn temperatures_are_stretched_without_a_range() {
+        let image = Renderer::new(palette()).render(&arr2(&[[10.0, 15.0, 20.0, f32::NAN]]));
+
+        assert_eq!(
+            colors(&image),
+            [[0, 0, 0], [128, 128, 128], [255, 255, 255], MISSING_COLOR]
+        );
+    }
+
+    #[test]
+    fn ranges_and_isotherms_take_their_colors() {
+        let renderer = Renderer::new(palette())
+            .range(0.0, 100.0)
+            .camera_range(-20.0, 150.0)
+            .isotherm1(40.0, 60.0)
+            .isotherm2(55.0, 70.0);
+        let temperatures = arr2(&[[-30.0, -10.0, 0.0, 50.0, 58.0, 65.0, 100.0, 120.0, 200.0]]);
+
+        assert_eq!(
+            colors(&renderer.render(&temperatures)),
+            [
+                [4, 0, 0],
+                [2, 0, 0],
+                [0, 0, 0],
+                [5, 0, 0],
+                [5, 0, 0],
+                [6, 0, 0],
+                [255, 255, 255],
+                [1, 0, 0],
+                [3, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_palette_uses_the_missing_color() {
+        let palette = Palette {
+            colors: vec![],
+            ..palette()
+        };
+        assert_eq!(palette.color_at(0.5), MISSING_COLOR);
+    }
+}
@@ -0,0 +1,149 @@
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::VecDeque;
+
+use crate::csq::CSQReader;
+use crate::error::{Error, Result};
+use crate::types::DecodedFrame;
+
+/// The number of frames read ahead per worker thread.
+const FRAMES_PER_THREAD: usize = 2;
+
+/// A decoded frame together with its index, offset and length, which are needed to report it
+/// if it was damaged.
+type Decoded<T> = (usize, u64, u64, Result<T>);
+
+/// Iterator over frames that are decoded on a thread pool, in the order of the file.
+///
+/// Frames are split off the file in batches of a few frames per thread, which are decoded
+/// concurrently and then returned one by one before the next batch is read.
+pub struct ParallelFrames<'a, T> {
+    reader: &'a mut CSQReader,
+    pool: ThreadPool,
+    batch_size: usize,
+    decoded: VecDeque<Decoded<T>>,
+    /// An error splitting off the next frame, returned after the frames before it.
+    error: Option<Error>,
+    done: bool,
+}
+
+impl<'a, T: DecodedFrame> ParallelFrames<'a, T> {
+    pub(crate) fn new(reader: &'a mut CSQReader, threads: usize) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        let batch_size = pool.current_num_threads() * FRAMES_PER_THREAD;
+
+        Ok(Self {
+            reader,
+            pool,
+            batch_size,
+            decoded: VecDeque::with_capacity(batch_size),
+            error: None,
+            done: false,
+        })
+    }
+
+    fn decode_batch(&mut self) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size {
+            match self.reader.next_frame_data() {
+                Ok(Some(data)) => batch.push(data),
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                Err(e) => {
+                    // Frames split off before the error are still returned first.
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let overrides = self.reader.metadata_overrides();
+        let decoded: Vec<Decoded<T>> = self.pool.install(|| {
+            batch
+                .into_par_iter()
+                .map(|(index, offset, img)| {
+                    let frame = CSQReader::extract_data(index, offset, &img, overrides)
+                        .and_then(T::from_raw);
+                    (index, offset, img.len() as u64, frame)
+                })
+                .collect()
+        });
+        self.decoded.extend(decoded);
+    }
+}
+
+impl<T: DecodedFrame> Iterator for ParallelFrames<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.decoded.is_empty() {
+                // Like `CSQReader::frames`, reading continues after an error splitting off a
+                // frame was returned.
+                if let Some(e) = self.error.take() {
+                    return Some(Err(e));
+                }
+                if self.done {
+                    return None;
+                }
+                self.decode_batch();
+            }
+
+            // The recovery policy is applied here rather than on the workers, so placeholders
+            // are based on the frame before them in the file.
+            let Some((index, offset, length, frame)) = self.decoded.pop_front() else {
+                continue;
+            };
+            match self.reader.recover(index, offset, length, frame) {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csq::tests::damaged_file;
+    use crate::csq::CSQReader;
+    use crate::types::{RawFrame, RecoveryPolicy};
+
+    /// The parts of a result that are compared between sequential and parallel reading.
+    fn summary(frame: crate::Result<RawFrame>) -> Result<(usize, u64, bool, Vec<u16>), String> {
+        frame
+            .map(|f| (f.index, f.offset, f.placeholder, f.raw.into_raw_vec()))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn frames_match_sequential_reading() {
+        for policy in [
+            RecoveryPolicy::Abort,
+            RecoveryPolicy::Skip,
+            RecoveryPolicy::Placeholder,
+        ] {
+            let (mut sequential, path, _, _) = damaged_file(policy);
+            let mut parallel = CSQReader::new(&path).unwrap();
+            parallel.set_recovery_policy(policy);
+
+            let expected: Vec<_> = sequential.raw_frames().map(summary).collect();
+            let frames: Vec<_> = parallel
+                .parallel_raw_frames(2)
+                .unwrap()
+                .map(summary)
+                .collect();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(frames, expected, "{:?}", policy);
+            assert_eq!(
+                parallel.skipped_frames(),
+                sequential.skipped_frames(),
+                "{:?}",
+                policy
+            );
+        }
+    }
+}
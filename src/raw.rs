@@ -0,0 +1,193 @@
+use ndarray::Array2;
+use std::io::Cursor;
+use tiff::decoder::{Decoder, DecodingResult};
+
+use crate::error::{Error, Result};
+use crate::fff::{ByteOrder, FFFData};
+use crate::jpegls;
+
+/// Decodes the raw thermal image of a FFF container with the codec of its
+/// `RawThermalImageType`, into an array of shape (height, width).
+pub fn decode(fff: &FFFData, width: usize, height: usize) -> Result<Array2<u16>> {
+    let image_type = fff
+        .tags
+        .get("RawThermalImageType")
+        .map(String::as_str)
+        .unwrap_or("unknown");
+
+    match image_type {
+        "JPG" => jpegls::decode(fff.raw_thermal_image),
+        "PNG" => decode_png(fff.raw_thermal_image),
+        "TIFF" if is_tiff(fff.raw_thermal_image) => decode_tiff(fff.raw_thermal_image),
+        // exiftool calls uncompressed images TIFF too, as it wraps them in a TIFF header.
+        "TIFF" => decode_uncompressed(fff.raw_thermal_image, fff.raw_byte_order, width, height),
+        other => Err(Error::UnsupportedRawImageType(other.to_string())),
+    }
+}
+
+/// FLIR writes 16 bit PNGs with the bytes of every value in little endian order, instead of the
+/// big endian order of the PNG specification.
+fn decode_png(data: &[u8]) -> Result<Array2<u16>> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+
+    if info.color_type != png::ColorType::Grayscale {
+        return Err(Error::DecodeFailed(format!(
+            "Unsupported PNG color type {:?}",
+            info.color_type
+        )));
+    }
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let values: Vec<u16> = match info.bit_depth {
+        png::BitDepth::Sixteen => buffer[..info.buffer_size()]
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect(),
+        png::BitDepth::Eight => buffer[..info.buffer_size()]
+            .iter()
+            .map(|&b| b as u16)
+            .collect(),
+        other => {
+            return Err(Error::DecodeFailed(format!(
+                "Unsupported PNG bit depth {:?}",
+                other
+            )))
+        }
+    };
+
+    to_array(values, width, height)
+}
+
+fn is_tiff(data: &[u8]) -> bool {
+    data.starts_with(b"II*\0") || data.starts_with(b"MM\0*")
+}
+
+fn decode_tiff(data: &[u8]) -> Result<Array2<u16>> {
+    let mut decoder =
+        Decoder::new(Cursor::new(data)).map_err(|e| Error::DecodeFailed(e.to_string()))?;
+    let (width, height) = decoder
+        .dimensions()
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+
+    let values = match decoder
+        .read_image()
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?
+    {
+        DecodingResult::U16(values) => values,
+        DecodingResult::U8(values) => values.into_iter().map(u16::from).collect(),
+        _ => {
+            return Err(Error::DecodeFailed(
+                "Unsupported TIFF sample format, expected 8 or 16 bit integers".into(),
+            ))
+        }
+    };
+
+    to_array(values, width as usize, height as usize)
+}
+
+fn decode_uncompressed(
+    data: &[u8],
+    order: ByteOrder,
+    width: usize,
+    height: usize,
+) -> Result<Array2<u16>> {
+    let len = width * height * 2;
+    let data = data.get(..len).ok_or_else(|| {
+        Error::TruncatedFrame(format!(
+            "Uncompressed {}x{} raw thermal image needs {} bytes, but only {} are present",
+            width,
+            height,
+            len,
+            data.len()
+        ))
+    })?;
+
+    let values = data
+        .chunks_exact(2)
+        .map(|b| match order {
+            ByteOrder::Little => u16::from_le_bytes([b[0], b[1]]),
+            ByteOrder::Big => u16::from_be_bytes([b[0], b[1]]),
+        })
+        .collect();
+
+    to_array(values, width, height)
+}
+
+fn to_array(values: Vec<u16>, width: usize, height: usize) -> Result<Array2<u16>> {
+    Array2::from_shape_vec((height, width), values).map_err(|e| Error::DecodeFailed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fff::tests::raw_fff;
+    use ndarray::arr2;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    /// A 16 bit grayscale PNG with the bytes of every value swapped, like FLIR writes them.
+    fn flir_png(width: u32, height: u32, values: &[u16]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let mut writer = encoder.write_header().unwrap();
+        let pixels: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn png_values_are_byte_swapped() {
+        let data = raw_fff(2, 1, &flir_png(2, 1, &[0x1234, 0xabcd]));
+
+        let fff = FFFData::parse(&data).unwrap();
+        assert_eq!(fff.tags["RawThermalImageType"], "PNG");
+        assert_eq!(fff.decode_raw().unwrap(), arr2(&[[0x1234, 0xabcd]]));
+    }
+
+    #[test]
+    fn tiff_images_are_decoded() {
+        let mut tiff = Cursor::new(vec![]);
+        TiffEncoder::new(&mut tiff)
+            .unwrap()
+            .write_image::<colortype::Gray16>(2, 2, &[1, 2, 3, 40000])
+            .unwrap();
+        let data = raw_fff(2, 2, tiff.get_ref());
+
+        let fff = FFFData::parse(&data).unwrap();
+        assert_eq!(fff.tags["RawThermalImageType"], "TIFF");
+        assert_eq!(fff.decode_raw().unwrap(), arr2(&[[1, 2], [3, 40000]]));
+    }
+
+    #[test]
+    fn uncompressed_images_use_the_byte_order_of_the_record() {
+        let data = raw_fff(2, 1, &[0x34, 0x12, 0xcd, 0xab]);
+
+        let fff = FFFData::parse(&data).unwrap();
+        assert_eq!(fff.decode_raw().unwrap(), arr2(&[[0x1234, 0xabcd]]));
+
+        let short = raw_fff(3, 1, &[0x34, 0x12, 0xcd, 0xab]);
+        assert!(matches!(
+            FFFData::parse(&short).unwrap().decode_raw(),
+            Err(Error::TruncatedFrame(_))
+        ));
+    }
+
+    #[test]
+    fn size_must_match_the_raw_data_record() {
+        let data = raw_fff(3, 1, &flir_png(2, 1, &[1, 2]));
+
+        let error = FFFData::parse(&data).unwrap().decode_raw().unwrap_err();
+        assert!(matches!(error, Error::InvalidFrame(_)), "{}", error);
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::fff::decode_fff;
+use crate::types::Frame;
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const APP1: u8 = 0xe1;
+
+/// The APP1 segments holding FFF data start with this header, followed by the index of the
+/// segment and the index of the last segment.
+const FLIR_SEGMENT_HEADER: &[u8] = b"FLIR\0\x01";
+const FLIR_SEGMENT_DATA_START: usize = 8;
+
+/// Reads a radiometric JPEG still from a FLIR camera and converts it to temperatures.
+pub fn read_radiometric_jpeg(path: &Path) -> Result<Frame> {
+    decode_radiometric_jpeg(&fs::read(path)?)
+}
+
+/// Like `read_radiometric_jpeg`, but for a JPEG that was already read.
+pub fn decode_radiometric_jpeg(jpeg: &[u8]) -> Result<Frame> {
+    decode_fff(&extract_fff(jpeg)?)
+}
+
+/// Reassembles the FFF data that FLIR cameras split across the APP1 "FLIR" segments of a JPEG.
+pub fn extract_fff(jpeg: &[u8]) -> Result<Vec<u8>> {
+    if !jpeg.starts_with(&[0xff, SOI]) {
+        return Err(Error::NotARadiometricJpeg("not a JPEG file".into()));
+    }
+
+    let mut chunks = vec![];
+    let mut pos = 2;
+    while let Some(&[0xff, marker]) = jpeg.get(pos..pos + 2) {
+        // Markers may be padded with any number of 0xff bytes.
+        if marker == 0xff {
+            pos += 1;
+            continue;
+        }
+        if marker == SOS || marker == EOI {
+            break;
+        }
+
+        let Some(&[hi, lo]) = jpeg.get(pos + 2..pos + 4) else {
+            break;
+        };
+        let len = u16::from_be_bytes([hi, lo]) as usize;
+        let Some(segment) = jpeg.get(pos + 4..pos + 2 + len) else {
+            return Err(Error::TruncatedFrame(format!(
+                "JPEG segment at {:#x} extends beyond end of data",
+                pos
+            )));
+        };
+
+        if marker == APP1 && segment.starts_with(FLIR_SEGMENT_HEADER) {
+            if let Some(data) = segment.get(FLIR_SEGMENT_DATA_START..) {
+                chunks.push((segment[6], segment[7], data));
+            }
+        }
+
+        pos += 2 + len;
+    }
+
+    if chunks.is_empty() {
+        return Err(Error::NotARadiometricJpeg(
+            "no FLIR segments found in JPEG".into(),
+        ));
+    }
+
+    chunks.sort_by_key(|&(index, _, _)| index);
+    let last = chunks[0].1;
+    let complete = chunks.len() == last as usize + 1
+        && chunks
+            .iter()
+            .enumerate()
+            .all(|(i, &(index, _, _))| index as usize == i);
+    if !complete {
+        return Err(Error::TruncatedFrame(format!(
+            "JPEG contains {} of {} FLIR segments",
+            chunks.len(),
+            last as usize + 1
+        )));
+    }
+
+    Ok(chunks
+        .into_iter()
+        .flat_map(|(_, _, data)| data)
+        .copied()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fff::tests::seq_frame;
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let len = (data.len() + 2) as u16;
+        [&[0xff, marker], len.to_be_bytes().as_slice(), data].concat()
+    }
+
+    fn flir_segment(index: u8, last: u8, data: &[u8]) -> Vec<u8> {
+        segment(APP1, &[FLIR_SEGMENT_HEADER, &[index, last], data].concat())
+    }
+
+    /// A JPEG with `segments` between an APP0 segment and the image data.
+    fn jpeg_with(segments: &[Vec<u8>]) -> Vec<u8> {
+        [
+            vec![0xff, SOI],
+            segment(0xe0, b"JFIF\0"),
+            segments.concat(),
+            segment(SOS, &[0; 4]),
+            vec![0xff, APP1, 0xff, 0xff, 0xff, EOI],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn segments_are_reassembled_in_order() {
+        let jpeg = jpeg_with(&[
+            flir_segment(2, 2, b"ghi"),
+            segment(APP1, b"Exif\0\0"),
+            flir_segment(0, 2, b"abc"),
+            vec![0xff, 0xff],
+            flir_segment(1, 2, b"def"),
+        ]);
+
+        assert_eq!(extract_fff(&jpeg).unwrap(), b"abcdefghi");
+    }
+
+    #[test]
+    fn missing_segments_are_rejected() {
+        let jpeg = jpeg_with(&[flir_segment(0, 2, b"abc"), flir_segment(2, 2, b"ghi")]);
+        assert!(matches!(extract_fff(&jpeg), Err(Error::TruncatedFrame(_))));
+
+        let plain = jpeg_with(&[segment(APP1, b"Exif\0\0")]);
+        assert!(matches!(
+            extract_fff(&plain),
+            Err(Error::NotARadiometricJpeg(_))
+        ));
+        assert!(matches!(
+            extract_fff(b"\x89PNG"),
+            Err(Error::NotARadiometricJpeg(_))
+        ));
+
+        let mut truncated = jpeg_with(&[flir_segment(0, 0, b"abc")]);
+        truncated.truncate(20);
+        assert!(matches!(
+            extract_fff(&truncated),
+            Err(Error::TruncatedFrame(_))
+        ));
+    }
+
+    #[test]
+    fn split_fff_data_is_decoded() {
+        let fff = seq_frame(&[10000, 20000]);
+        let (first, second) = fff.split_at(fff.len() / 2);
+        let jpeg = jpeg_with(&[flir_segment(0, 1, first), flir_segment(1, 1, second)]);
+
+        let frame = decode_radiometric_jpeg(&jpeg).unwrap();
+
+        assert_eq!(frame.raw, ndarray::arr2(&[[10000, 20000]]));
+        assert_eq!(frame.temperatures.dim(), (1, 2));
+    }
+}
@@ -0,0 +1,421 @@
+use crate::error::{Error, Result};
+use crate::fff;
+use lazy_static::lazy_static;
+use pcre2::bytes::Regex;
+use std::io::{Read, Seek, SeekFrom};
+use std::str;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Smaller in tests, so that frames span several reads.
+const BLOCKSIZE: usize = if cfg!(test) { 64 } else { 1000000 };
+const MAGIC_SEQUENCE_LEN: usize = 6;
+const SEQ_MAGIC_SEQUENCE_LEN: usize = 4;
+/// How often a followed file is checked for new data.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+lazy_static! {
+    static ref MAGIC_SEQUENCE: Regex =
+        Regex::new(str::from_utf8(b"\x46\x46\x46\x00\x52\x54").unwrap()).unwrap();
+    static ref SEQ_MAGIC_SEQUENCE: Regex =
+        Regex::new(str::from_utf8(b"\x46\x46\x46\x00").unwrap()).unwrap();
+}
+
+/// The container formats that consist of concatenated FFF frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    /// FLIR CSQ video, with JPEG-LS compressed frames starting at every `MAGIC_SEQUENCE`.
+    #[default]
+    Csq,
+    /// Legacy FLIR and ResearchIR SEQ sequences, with uncompressed or PNG compressed frames.
+    /// Only the first four bytes of the magic sequence are fixed, so every match is checked for
+    /// a valid FFF header.
+    Seq,
+}
+
+impl FileFormat {
+    /// Detects the format from the start of a stream, and rewinds it afterwards. Returns `None`
+    /// if the stream is too short to tell, e.g. when a recording was just started.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+        let mut header = Vec::with_capacity(fff::HEADER_SIZE);
+        reader
+            .by_ref()
+            .take(fff::HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        Ok((header.len() == fff::HEADER_SIZE).then(|| Self::from_header(&header)))
+    }
+
+    fn from_header(header: &[u8]) -> Self {
+        if !header.starts_with(b"FFF\0RT") && fff::is_header(header) {
+            FileFormat::Seq
+        } else {
+            FileFormat::Csq
+        }
+    }
+
+    fn magic_sequence(self) -> &'static Regex {
+        match self {
+            FileFormat::Csq => &MAGIC_SEQUENCE,
+            FileFormat::Seq => &SEQ_MAGIC_SEQUENCE,
+        }
+    }
+
+    /// The number of bytes from the start of a frame that are needed to recognize it.
+    fn match_len(self) -> usize {
+        match self {
+            FileFormat::Csq => MAGIC_SEQUENCE_LEN,
+            FileFormat::Seq => fff::HEADER_SIZE.max(SEQ_MAGIC_SEQUENCE_LEN),
+        }
+    }
+}
+
+/// Splits a stream into frames at every magic sequence of its `FileFormat`.
+///
+/// Data is read in blocks of `BLOCKSIZE` until the start of the next frame is found, so frames
+/// may be of any size and magic sequences split across two reads are still found. The last frame
+/// of the stream is the data following the last magic sequence.
+///
+/// When following a stream that is still being written, reaching the end of the stream waits for
+/// more data instead, as the last frame may not be complete yet. The last frame is only returned
+/// once no data was added for the idle timeout.
+#[derive(Debug, Default)]
+pub struct FrameSplitter {
+    buffer: Vec<u8>,
+    /// The start of the data in `buffer` that was not returned yet. The bytes before it are only
+    /// removed when more data is read, instead of moving the buffer for every frame.
+    start: usize,
+    /// The offset of the first byte of `data()` within the stream.
+    offset: u64,
+    /// Everything before this position in `data()` was already searched for the next frame.
+    searched: usize,
+    /// Whether `data()` starts with a magic sequence.
+    in_frame: bool,
+    found_frame: bool,
+    discarded: bool,
+    eof: bool,
+    /// The idle timeout when following the stream.
+    follow: Option<Duration>,
+    /// When the end of the followed stream was first reached without new data since.
+    waiting_since: Option<Instant>,
+    /// Detected from the start of the stream if `None`.
+    format: Option<FileFormat>,
+}
+
+impl FrameSplitter {
+    /// Creates a splitter for a stream positioned at `offset`.
+    pub fn new(offset: u64) -> Self {
+        Self {
+            offset,
+            ..Default::default()
+        }
+    }
+
+    /// Splits the stream at the magic sequences of `format`, or of the format detected from
+    /// the start of the stream if `None`.
+    pub fn format(mut self, format: Option<FileFormat>) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The format of the stream, once it is known.
+    pub fn detected_format(&self) -> Option<FileFormat> {
+        self.format
+    }
+
+    /// Waits for more data at the end of the stream, until none was added for `idle_timeout`.
+    pub fn follow(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.follow = idle_timeout;
+        self
+    }
+
+    /// Returns the next frame and its offset within the stream.
+    pub fn next_frame<R: Read>(&mut self, reader: &mut R) -> Result<Option<(u64, Vec<u8>)>> {
+        let Some(len) = self.next_frame_len(reader)? else {
+            return Ok(None);
+        };
+
+        let offset = self.offset;
+        let frame = self.data()[..len].to_vec();
+        self.consume(len);
+
+        Ok(Some((offset, frame)))
+    }
+
+    /// Skips over the next frame without copying it, returning its offset and length.
+    pub fn skip_frame<R: Read>(&mut self, reader: &mut R) -> Result<Option<(u64, u64)>> {
+        let Some(len) = self.next_frame_len(reader)? else {
+            return Ok(None);
+        };
+
+        let offset = self.offset;
+        self.consume(len);
+
+        Ok(Some((offset, len as u64)))
+    }
+
+    /// Reads until the frame at the start of `data()` is complete and returns its length.
+    fn next_frame_len<R: Read>(&mut self, reader: &mut R) -> Result<Option<usize>> {
+        loop {
+            if self.format.is_none() {
+                if self.data().len() < fff::HEADER_SIZE && !self.eof {
+                    self.fill(reader)?;
+                    continue;
+                }
+                self.format = Some(FileFormat::from_header(self.data()));
+            }
+
+            if !self.in_frame {
+                self.skip_to_frame_start()?;
+            }
+
+            if self.in_frame {
+                if let Some(end) = self.find_magic(self.searched.max(1))? {
+                    return Ok(Some(end));
+                }
+                self.searched = self
+                    .data()
+                    .len()
+                    .saturating_sub(self.current_format().match_len() - 1);
+            }
+
+            if self.eof {
+                if self.in_frame {
+                    self.in_frame = false;
+                    return Ok(Some(self.data().len()));
+                }
+                if self.discarded && !self.found_frame {
+                    // Only reported once, afterwards the stream just ends.
+                    self.discarded = false;
+                    return Err(Error::NotACsqFile("no frame found in data".into()));
+                }
+                return Ok(None);
+            }
+
+            self.fill(reader)?;
+        }
+    }
+
+    fn skip_to_frame_start(&mut self) -> Result<()> {
+        match self.find_magic(0)? {
+            Some(start) => {
+                self.discard(start);
+                self.in_frame = true;
+                self.found_frame = true;
+            }
+            None => {
+                // Keep the end, it could be the beginning of a magic sequence.
+                let len = self.data().len();
+                let keep = (self.current_format().match_len() - 1).min(len);
+                self.discard(len - keep);
+            }
+        }
+        Ok(())
+    }
+
+    /// The data that was read but not returned yet.
+    fn data(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+
+    fn current_format(&self) -> FileFormat {
+        self.format.unwrap_or_default()
+    }
+
+    /// Returns `true` if the stream should be read again, after waiting for it to grow.
+    fn wait_for_data(&mut self) -> bool {
+        let Some(idle_timeout) = self.follow else {
+            return false;
+        };
+
+        let waiting_since = *self.waiting_since.get_or_insert_with(Instant::now);
+        if waiting_since.elapsed() >= idle_timeout {
+            return false;
+        }
+
+        thread::sleep(FOLLOW_POLL_INTERVAL);
+        true
+    }
+
+    /// Finds the next frame start at or after `start`. Matches too close to the end of `buffer`
+    /// to be recognized are only returned at the end of the stream.
+    fn find_magic(&self, mut start: usize) -> Result<Option<usize>> {
+        let format = self.current_format();
+        let data = self.data();
+        while start < data.len() {
+            let Some(m) = format
+                .magic_sequence()
+                .find_at(data, start)
+                .map_err(std::io::Error::other)?
+            else {
+                return Ok(None);
+            };
+
+            let candidate = &data[m.start()..];
+            if candidate.len() < format.match_len() {
+                return Ok(self.eof.then_some(m.start()));
+            }
+            if format == FileFormat::Csq || fff::is_header(candidate) {
+                return Ok(Some(m.start()));
+            }
+            start = m.start() + 1;
+        }
+        Ok(None)
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+        self.offset += len as u64;
+        self.searched = 0;
+    }
+
+    fn discard(&mut self, len: usize) {
+        if len > 0 {
+            self.consume(len);
+            self.discarded = true;
+        }
+    }
+
+    fn fill<R: Read>(&mut self, reader: &mut R) -> Result<()> {
+        self.buffer.drain(..self.start);
+        self.start = 0;
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + BLOCKSIZE, 0);
+
+        let read_amount = reader.read(&mut self.buffer[len..])?;
+        self.buffer.truncate(len + read_amount);
+
+        if read_amount > 0 {
+            self.waiting_since = None;
+        } else if !self.wait_for_data() {
+            self.eof = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fff::tests::{csq_frame, seq_frame};
+    use std::io::Cursor;
+
+    /// Returns at most `chunk` bytes per read, to split the data at every position.
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn split<R: Read>(mut reader: R) -> Vec<(u64, Vec<u8>)> {
+        let mut splitter = FrameSplitter::new(0);
+        std::iter::from_fn(|| splitter.next_frame(&mut reader).unwrap()).collect()
+    }
+
+    fn frame(fill: u8, len: usize) -> Vec<u8> {
+        [b"FFF\0RT".as_slice(), &vec![fill; len]].concat()
+    }
+
+    #[test]
+    fn frames_larger_than_a_block_are_split() {
+        let frames = [
+            frame(1, BLOCKSIZE * 3),
+            frame(2, 10),
+            frame(3, BLOCKSIZE + 1),
+        ];
+
+        let split = split(Cursor::new(frames.concat()));
+
+        assert_eq!(split.len(), 3);
+        let mut offset = 0;
+        for ((frame_offset, data), frame) in split.iter().zip(&frames) {
+            assert_eq!(*frame_offset, offset);
+            assert_eq!(data, frame);
+            offset += frame.len() as u64;
+        }
+    }
+
+    #[test]
+    fn magic_sequences_split_across_reads_are_found() {
+        let frames = [frame(1, 20), frame(2, 5), frame(3, 20)];
+        let data = [b"junk".as_slice(), &frames.concat()].concat();
+
+        for chunk in 1..=MAGIC_SEQUENCE_LEN + 1 {
+            let split = split(Chunked { data: &data, chunk });
+            let offsets: Vec<u64> = split.iter().map(|(offset, _)| *offset).collect();
+            assert_eq!(offsets, [4, 30, 41], "chunk size {}", chunk);
+        }
+    }
+
+    #[test]
+    fn last_frame_ends_at_the_end_of_the_stream() {
+        let data = [frame(1, 8), frame(2, 3)].concat();
+
+        let split = split(Cursor::new(&data));
+
+        assert_eq!(split.last(), Some(&(14, frame(2, 3))));
+
+        // A magic sequence at the very end is a frame of its own.
+        let split = self::split(Cursor::new(b"FFF\0RTFFF\0RT"));
+        assert_eq!(split, [(0, b"FFF\0RT".to_vec()), (6, b"FFF\0RT".to_vec())]);
+    }
+
+    #[test]
+    fn stream_without_frames_is_rejected_once() {
+        let mut reader = Cursor::new(vec![b'x'; BLOCKSIZE * 2]);
+        let mut splitter = FrameSplitter::new(0);
+
+        assert!(matches!(
+            splitter.next_frame(&mut reader),
+            Err(Error::NotACsqFile(_))
+        ));
+        assert!(matches!(splitter.next_frame(&mut reader), Ok(None)));
+    }
+
+    #[test]
+    fn seq_frames_are_only_split_at_valid_headers() {
+        // The values are "FFF\0" in little endian, but not followed by a FFF header.
+        let frames = [seq_frame(&[0x4646, 0x0046, 7]), seq_frame(&[1, 2, 3])];
+        let data = frames.concat();
+
+        let mut splitter = FrameSplitter::new(0);
+        let mut reader = Chunked {
+            data: &data,
+            chunk: 5,
+        };
+        let first = splitter.next_frame(&mut reader).unwrap();
+        let second = splitter.next_frame(&mut reader).unwrap();
+
+        assert_eq!(splitter.detected_format(), Some(FileFormat::Seq));
+        assert_eq!(first, Some((0, frames[0].clone())));
+        assert_eq!(second, Some((frames[0].len() as u64, frames[1].clone())));
+        assert_eq!(splitter.next_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn format_is_detected_from_the_first_header() {
+        let detect = |data: &[u8]| FileFormat::detect(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(detect(&seq_frame(&[1, 2])), Some(FileFormat::Seq));
+        assert_eq!(detect(&csq_frame(&[1, 2])), Some(FileFormat::Csq));
+        // Anything else is split as CSQ, which reports that no frame was found.
+        assert_eq!(detect(&[b'x'; fff::HEADER_SIZE]), Some(FileFormat::Csq));
+        assert_eq!(detect(&seq_frame(&[1, 2])[..fff::HEADER_SIZE - 1]), None);
+
+        let mut reader = Cursor::new(seq_frame(&[1, 2]));
+        reader.set_position(10);
+        FileFormat::detect(&mut reader).unwrap();
+        assert_eq!(reader.position(), 0);
+    }
+}
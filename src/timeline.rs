@@ -0,0 +1,275 @@
+use chrono::{DateTime, FixedOffset, TimeDelta, TimeZone};
+
+/// Intervals longer than this many frame periods are reported as dropped frames.
+const GAP_THRESHOLD: f64 = 1.5;
+
+/// A gap in a recording where the camera dropped frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameGap {
+    /// The index of the last frame before the gap.
+    pub after: usize,
+    /// The capture time of the last frame before the gap.
+    pub start: DateTime<FixedOffset>,
+    /// The time between the frames before and after the gap.
+    pub duration: TimeDelta,
+    /// The estimated number of frames that are missing.
+    pub dropped: usize,
+}
+
+/// The nominal frame rate of a recording compared with the rate it was actually captured at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameRateReport {
+    /// The frame rate the camera was set to, in frames per second.
+    pub nominal: Option<f64>,
+    /// The average rate the frames were captured at, in frames per second.
+    pub effective: Option<f64>,
+    /// The gaps where frames were dropped.
+    pub gaps: Vec<FrameGap>,
+}
+
+impl FrameRateReport {
+    /// The total number of dropped frames.
+    pub fn dropped(&self) -> usize {
+        self.gaps.iter().map(|gap| gap.dropped).sum()
+    }
+}
+
+/// The capture times of the frames of a recording.
+///
+/// Frames whose header is damaged have no capture time and are left out of the lookups. The
+/// capture times are assumed to increase with the frame index, as they do in recordings.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    timestamps: Vec<Option<DateTime<FixedOffset>>>,
+    /// The frames that have a capture time, in file order.
+    captured: Vec<(usize, DateTime<FixedOffset>)>,
+}
+
+impl Timeline {
+    pub(crate) fn new(timestamps: Vec<Option<DateTime<FixedOffset>>>) -> Self {
+        let captured = timestamps
+            .iter()
+            .enumerate()
+            .filter_map(|(index, timestamp)| timestamp.map(|t| (index, t)))
+            .collect();
+
+        Self {
+            timestamps,
+            captured,
+        }
+    }
+
+    /// The number of frames, including those without a capture time.
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// The capture time of the first frame.
+    pub fn start(&self) -> Option<DateTime<FixedOffset>> {
+        self.captured.first().map(|&(_, t)| t)
+    }
+
+    /// The capture time of the last frame.
+    pub fn end(&self) -> Option<DateTime<FixedOffset>> {
+        self.captured.last().map(|&(_, t)| t)
+    }
+
+    /// The time between the capture of the first and the last frame.
+    pub fn duration(&self) -> Option<TimeDelta> {
+        Some(self.end()? - self.start()?)
+    }
+
+    /// The capture time of the frame at `index`.
+    pub fn timestamp(&self, index: usize) -> Option<DateTime<FixedOffset>> {
+        self.timestamps.get(index).copied().flatten()
+    }
+
+    /// The capture time of the frame at `index`, relative to the start of the recording.
+    pub fn elapsed(&self, index: usize) -> Option<TimeDelta> {
+        Some(self.timestamp(index)? - self.start()?)
+    }
+
+    /// The last frame captured at or before `time`, which is the frame that was current at
+    /// that time. Returns `None` if `time` is before the recording started.
+    pub fn frame_at<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> Option<usize> {
+        let after = self.captured.partition_point(|(_, t)| t <= time);
+        after.checked_sub(1).map(|i| self.captured[i].0)
+    }
+
+    /// The frame captured closest to `time`.
+    pub fn nearest_frame<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> Option<usize> {
+        let after = self.captured.partition_point(|(_, t)| t <= time);
+
+        let distance = |&(_, t): &(usize, DateTime<FixedOffset>)| {
+            (t.with_timezone(&time.timezone()) - time.clone()).abs()
+        };
+        let before = after.checked_sub(1).and_then(|i| self.captured.get(i));
+        match (before, self.captured.get(after)) {
+            (Some(b), Some(a)) if distance(a) < distance(b) => Some(a.0),
+            (Some(b), _) => Some(b.0),
+            (None, a) => a.map(|a| a.0),
+        }
+    }
+
+    /// The average rate the frames were captured at, in frames per second.
+    pub fn effective_frame_rate(&self) -> Option<f64> {
+        let (&(first, start), &(last, end)) = (self.captured.first()?, self.captured.last()?);
+        let seconds = seconds(end - start);
+        (seconds > 0.0).then(|| (last - first) as f64 / seconds)
+    }
+
+    /// Finds the gaps where frames were dropped, by comparing the capture times with the frame
+    /// period. Without a nominal frame rate, the median interval between frames is used.
+    pub fn gaps(&self, nominal_frame_rate: Option<f64>) -> Vec<FrameGap> {
+        let Some(period) = nominal_frame_rate
+            .filter(|&rate| rate > 0.0)
+            .map(|rate| 1.0 / rate)
+            .or_else(|| self.median_period())
+        else {
+            return vec![];
+        };
+
+        self.captured
+            .windows(2)
+            .filter_map(|pair| {
+                let [(before, start), (after, end)] = [pair[0], pair[1]];
+                let duration = end - start;
+                // Frames without a capture time in between were not dropped.
+                let frames = (after - before) as f64;
+                let periods = seconds(duration) / period;
+                if periods < frames * GAP_THRESHOLD {
+                    return None;
+                }
+
+                Some(FrameGap {
+                    after: before,
+                    start,
+                    duration,
+                    dropped: (periods.round() - frames).max(1.0) as usize,
+                })
+            })
+            .collect()
+    }
+
+    /// Compares the nominal frame rate with the capture times of the frames.
+    pub fn frame_rate_report(&self, nominal_frame_rate: Option<f64>) -> FrameRateReport {
+        FrameRateReport {
+            nominal: nominal_frame_rate,
+            effective: self.effective_frame_rate(),
+            gaps: self.gaps(nominal_frame_rate),
+        }
+    }
+
+    /// The median time between consecutive frames, in seconds.
+    fn median_period(&self) -> Option<f64> {
+        let mut periods: Vec<f64> = self
+            .captured
+            .windows(2)
+            .map(|pair| seconds(pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0) as f64)
+            .filter(|&period| period > 0.0)
+            .collect();
+        periods.sort_by(f64::total_cmp);
+        periods.get(periods.len() / 2).copied()
+    }
+
+    /// Iterates over the frame indices and capture times of the frames that have one.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, DateTime<FixedOffset>)> + '_ {
+        self.captured.iter().copied()
+    }
+}
+
+fn seconds(duration: TimeDelta) -> f64 {
+    duration.num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A timeline with frames captured at the given milliseconds after the start.
+    fn timeline(millis: &[Option<i64>]) -> Timeline {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap();
+        Timeline::new(
+            millis
+                .iter()
+                .map(|ms| ms.map(|ms| start + TimeDelta::milliseconds(ms)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn gaps_are_found_with_the_nominal_frame_rate() {
+        let timeline = timeline(&[Some(0), Some(100), Some(200), Some(500), Some(600)]);
+
+        let report = timeline.frame_rate_report(Some(10.0));
+
+        let [gap] = report.gaps[..] else {
+            panic!("expected one gap: {:?}", report.gaps);
+        };
+        assert_eq!(gap.after, 2);
+        assert_eq!(gap.start, timeline.timestamp(2).unwrap());
+        assert_eq!(gap.duration, TimeDelta::milliseconds(300));
+        assert_eq!(gap.dropped, 2);
+        assert_eq!(report.dropped(), 2);
+        assert_eq!(report.nominal, Some(10.0));
+        assert!((report.effective.unwrap() - 4.0 / 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gaps_are_found_with_the_median_period() {
+        // Without a nominal rate the median of 40 ms is used, so 80 ms is a gap and 50 ms not.
+        let timeline = timeline(&[Some(0), Some(40), Some(80), Some(130), Some(170), Some(250)]);
+
+        let gaps = timeline.gaps(None);
+
+        assert_eq!(gaps.len(), 1);
+        assert_eq!((gaps[0].after, gaps[0].dropped), (4, 1));
+    }
+
+    #[test]
+    fn intervals_below_the_threshold_are_no_gaps() {
+        let period = 100.0 * GAP_THRESHOLD;
+        let timeline = timeline(&[Some(0), Some(period as i64 - 1), Some(period as i64 + 99)]);
+
+        assert!(timeline.gaps(Some(10.0)).is_empty());
+    }
+
+    #[test]
+    fn frames_without_capture_time_are_not_dropped() {
+        let timeline = timeline(&[Some(0), None, Some(200), Some(300)]);
+
+        assert!(timeline.gaps(Some(10.0)).is_empty());
+        assert_eq!(timeline.len(), 4);
+        assert_eq!(timeline.timestamp(1), None);
+        assert_eq!(timeline.elapsed(2), Some(TimeDelta::milliseconds(200)));
+        assert!((timeline.effective_frame_rate().unwrap() - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn effective_rate_differs_from_a_wrong_nominal_rate() {
+        let timeline = timeline(&[Some(0), Some(100), Some(200)]);
+
+        let report = timeline.frame_rate_report(Some(30.0));
+
+        assert_eq!(report.nominal, Some(30.0));
+        assert!((report.effective.unwrap() - 10.0).abs() < 1e-9);
+        assert_eq!(report.dropped(), 4);
+    }
+
+    #[test]
+    fn frames_are_looked_up_by_time() {
+        let timeline = timeline(&[Some(0), Some(100), None, Some(300)]);
+        let at = |ms| timeline.start().unwrap() + TimeDelta::milliseconds(ms);
+
+        assert_eq!(timeline.duration(), Some(TimeDelta::milliseconds(300)));
+        assert_eq!(timeline.frame_at(&at(-1)), None);
+        assert_eq!(timeline.frame_at(&at(250)), Some(1));
+        assert_eq!(timeline.nearest_frame(&at(250)), Some(3));
+        assert_eq!(timeline.nearest_frame(&at(-50)), Some(0));
+        assert_eq!(timeline.nearest_frame(&at(1000)), Some(3));
+    }
+}
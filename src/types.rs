@@ -1,9 +1,247 @@
-use anyhow::Result;
-use std::collections::HashMap;
+use chrono::{DateTime, FixedOffset};
+use ndarray::Array2;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
 
 use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Serialize, Debug)]
+use crate::error::{Error, Result};
+use crate::fff::DATE_TIME_FORMAT;
+use crate::gps::parse_degrees;
+use crate::utils::raw_to_temp;
+
+const DEFAULT_EMISSIVITY: f32 = 0.95;
+const DEFAULT_OBJECT_DISTANCE: f32 = 1.0;
+const DEFAULT_TEMPERATURE: f32 = 20.0;
+const DEFAULT_IR_WINDOW_TRANSMISSION: f32 = 1.0;
+const DEFAULT_RELATIVE_HUMIDITY: f32 = 50.0;
+const DEFAULT_ATMOSPHERIC_TRANS_ALPHA1: f32 = 0.006569;
+const DEFAULT_ATMOSPHERIC_TRANS_ALPHA2: f32 = 0.01262;
+const DEFAULT_ATMOSPHERIC_TRANS_BETA1: f32 = -0.002276;
+const DEFAULT_ATMOSPHERIC_TRANS_BETA2: f32 = -0.00667;
+const DEFAULT_ATMOSPHERIC_TRANS_X: f32 = 1.9;
+
+/// A decoded frame of a CSQ file.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The position of the frame within the file, starting at 0.
+    pub index: usize,
+    /// The byte offset of the frame within the file.
+    pub offset: u64,
+    /// The time the frame was captured at, in the time zone of the camera.
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    /// The temperature of every pixel in degrees Celsius.
+    pub temperatures: Array2<f32>,
+    /// The raw sensor values the temperatures were calculated from.
+    pub raw: Array2<u16>,
+    /// The calibration and metadata the frame was decoded with. Every decoded frame has its own,
+    /// which clones of the frame share.
+    pub metadata: Arc<CSQExifData>,
+    /// Set if the frame was damaged and replaced under `RecoveryPolicy::Placeholder`. The raw
+    /// values are 0 and the temperatures NaN.
+    pub placeholder: bool,
+}
+
+/// A frame of a CSQ file that was decoded without converting it to temperatures.
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    /// The position of the frame within the file, starting at 0.
+    pub index: usize,
+    /// The byte offset of the frame within the file.
+    pub offset: u64,
+    /// The time the frame was captured at, in the time zone of the camera.
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    /// The raw sensor values.
+    pub raw: Array2<u16>,
+    /// The calibration and metadata needed to convert the raw values with `raw_to_temp`.
+    pub metadata: Arc<CSQExifData>,
+    /// Set if the frame was damaged and replaced under `RecoveryPolicy::Placeholder`.
+    pub placeholder: bool,
+}
+
+impl RawFrame {
+    /// A stand-in for a damaged frame, with the size and metadata of an earlier frame.
+    pub(crate) fn placeholder(
+        index: usize,
+        offset: u64,
+        shape: (usize, usize),
+        metadata: Arc<CSQExifData>,
+    ) -> Self {
+        Self {
+            index,
+            offset,
+            timestamp: None,
+            raw: Array2::zeros(shape),
+            metadata,
+            placeholder: true,
+        }
+    }
+
+    /// Converts the raw values to temperatures.
+    pub fn into_frame(self) -> Result<Frame> {
+        let temperatures = if self.placeholder {
+            Array2::from_elem(self.raw.dim(), f32::NAN)
+        } else {
+            raw_to_temp(&self.raw, &self.metadata)?
+        };
+
+        Ok(Frame {
+            index: self.index,
+            offset: self.offset,
+            timestamp: self.timestamp,
+            temperatures,
+            raw: self.raw,
+            metadata: self.metadata,
+            placeholder: self.placeholder,
+        })
+    }
+}
+
+/// Implemented by `Frame` and `RawFrame`, the types the reader can decode frames to.
+pub trait DecodedFrame: Sized + Send {
+    /// Finishes decoding a frame whose raw values were read.
+    fn from_raw(frame: RawFrame) -> Result<Self>;
+
+    /// The raw sensor values of the frame.
+    fn raw(&self) -> &Array2<u16>;
+
+    /// The calibration and metadata of the frame.
+    fn metadata(&self) -> &Arc<CSQExifData>;
+}
+
+impl DecodedFrame for Frame {
+    fn from_raw(frame: RawFrame) -> Result<Self> {
+        frame.into_frame()
+    }
+
+    fn raw(&self) -> &Array2<u16> {
+        &self.raw
+    }
+
+    fn metadata(&self) -> &Arc<CSQExifData> {
+        &self.metadata
+    }
+}
+
+impl DecodedFrame for RawFrame {
+    fn from_raw(frame: RawFrame) -> Result<Self> {
+        Ok(frame)
+    }
+
+    fn raw(&self) -> &Array2<u16> {
+        &self.raw
+    }
+
+    fn metadata(&self) -> &Arc<CSQExifData> {
+        &self.metadata
+    }
+}
+
+/// What the reader does when a frame cannot be read while iterating over the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Return the error. Iteration can continue with the next frame afterwards.
+    #[default]
+    Abort,
+    /// Leave out the damaged frame and continue at the next frame.
+    Skip,
+    /// Return a frame with `placeholder` set in place of the damaged frame, so frame indices
+    /// and timing are kept. Damaged frames before the first good frame are skipped, as there
+    /// is nothing to take the frame size from.
+    Placeholder,
+}
+
+/// A damaged frame that was skipped or replaced by a placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFrame {
+    /// The position of the frame within the file, starting at 0.
+    pub index: usize,
+    /// The byte offset of the frame within the file.
+    pub offset: u64,
+    /// The length of the frame in bytes.
+    pub length: u64,
+    /// Why the frame could not be read.
+    pub reason: String,
+}
+
+/// Values for the calibration and object parameters of `CSQExifData`, used when a file lacks
+/// them or stores them in a format that cannot be parsed. Temperatures are in degrees Celsius,
+/// distances in meters and the relative humidity in percent.
+///
+/// Every parameter is taken from the file if possible, then from these overrides, and then
+/// from the FLIR defaults: an emissivity of 0.95, an object distance of 1 m, reflected and
+/// atmospheric temperatures of 20 °C, a relative humidity of 50 %, no IR window, i.e. a
+/// transmission of 1 and the temperature of the atmosphere, and the atmospheric transmission
+/// constants FLIR uses for most cameras. The Planck constants are calibrated per camera, so
+/// they have no defaults, and a frame without them fails unless they are overridden.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataOverrides {
+    pub emissivity: Option<f32>,
+    pub object_distance: Option<f32>,
+    pub reflected_apparent_temperature: Option<f32>,
+    pub atmospheric_temperature: Option<f32>,
+    pub ir_window_temperature: Option<f32>,
+    pub ir_window_transmission: Option<f32>,
+    pub relative_humidity: Option<f32>,
+    pub planck_r1: Option<f32>,
+    pub planck_r2: Option<f32>,
+    pub planck_b: Option<f32>,
+    pub planck_f: Option<f32>,
+    pub planck_o: Option<f32>,
+    pub atmospheric_trans_alpha1: Option<f32>,
+    pub atmospheric_trans_alpha2: Option<f32>,
+    pub atmospheric_trans_beta1: Option<f32>,
+    pub atmospheric_trans_beta2: Option<f32>,
+    pub atmospheric_trans_x: Option<f32>,
+}
+
+impl MetadataOverrides {
+    /// The override of the parameter with the exiftool name `field`, e.g. `PlanckR1`.
+    fn field_mut(&mut self, field: &str) -> Option<&mut Option<f32>> {
+        Some(match field {
+            "Emissivity" => &mut self.emissivity,
+            "ObjectDistance" => &mut self.object_distance,
+            "ReflectedApparentTemperature" => &mut self.reflected_apparent_temperature,
+            "AtmosphericTemperature" => &mut self.atmospheric_temperature,
+            "IRWindowTemperature" => &mut self.ir_window_temperature,
+            "IRWindowTransmission" => &mut self.ir_window_transmission,
+            "RelativeHumidity" => &mut self.relative_humidity,
+            "PlanckR1" => &mut self.planck_r1,
+            "PlanckR2" => &mut self.planck_r2,
+            "PlanckB" => &mut self.planck_b,
+            "PlanckF" => &mut self.planck_f,
+            "PlanckO" => &mut self.planck_o,
+            "AtmosphericTransAlpha1" => &mut self.atmospheric_trans_alpha1,
+            "AtmosphericTransAlpha2" => &mut self.atmospheric_trans_alpha2,
+            "AtmosphericTransBeta1" => &mut self.atmospheric_trans_beta1,
+            "AtmosphericTransBeta2" => &mut self.atmospheric_trans_beta2,
+            "AtmosphericTransX" => &mut self.atmospheric_trans_x,
+            _ => return None,
+        })
+    }
+}
+
+/// Where an assumed value of `CSQExifData` came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    /// The value was set in `MetadataOverrides`.
+    Override,
+    /// The FLIR default was used.
+    Default,
+}
+
+/// A parameter that was missing from the file or could not be parsed, and the value that was
+/// used instead.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AssumedValue {
+    /// The exiftool name of the parameter, e.g. `IRWindowTransmission`.
+    pub field: String,
+    pub value: f32,
+    pub source: ValueSource,
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct CSQExifData {
     #[serde(rename = "OverflowColor")]
     pub overflow_color: Option<String>,
@@ -12,37 +250,40 @@ pub struct CSQExifData {
     #[serde(rename = "GPSImgDirectionRef")]
     pub gps_img_direction_ref: Option<String>,
     #[serde(rename = "FileInodeChangeDate/Time")]
-    pub file_inode_change_date_time: Option<String>,
+    pub file_inode_change_date_time: Option<DateTime<FixedOffset>>,
     #[serde(rename = "FilePermissions")]
     pub file_permissions: Option<String>,
     #[serde(rename = "AtmosphericTemperature")]
     pub atmospheric_temperature: f32,
     #[serde(rename = "LensPartNumber")]
     pub lens_part_number: Option<String>,
+    /// The lower limit of the measurement range in degrees Celsius.
     #[serde(rename = "CameraTemperatureRangeMin")]
-    pub camera_temperature_range_min: Option<String>,
+    pub camera_temperature_range_min: Option<f32>,
     #[serde(rename = "CameraSoftware")]
     pub camera_software: Option<String>,
     #[serde(rename = "FileName")]
     pub file_name: Option<String>,
     #[serde(rename = "FocusStepCount")]
-    pub focus_step_count: Option<String>,
+    pub focus_step_count: Option<u32>,
     #[serde(rename = "RawValueRangeMin")]
-    pub raw_value_range_min: Option<String>,
+    pub raw_value_range_min: Option<u16>,
     #[serde(rename = "GPSLatitudeRef")]
     pub gps_latitude_ref: Option<String>,
     #[serde(rename = "CreatorSoftware")]
     pub creator_software: Option<String>,
+    /// In degrees Celsius.
     #[serde(rename = "CameraTemperatureMaxWarn")]
-    pub camera_temperature_max_warn: Option<String>,
+    pub camera_temperature_max_warn: Option<f32>,
     #[serde(rename = "RawThermalImageType")]
     pub raw_thermal_image_type: Option<String>,
     #[serde(rename = "Directory")]
     pub directory: Option<String>,
     #[serde(rename = "FileSize")]
     pub file_size: Option<String>,
+    /// In degrees Celsius.
     #[serde(rename = "CameraTemperatureMinClip")]
-    pub camera_temperature_min_clip: Option<String>,
+    pub camera_temperature_min_clip: Option<f32>,
     #[serde(rename = "FilterSerialNumber")]
     pub filter_serial_number: Option<String>,
     #[serde(rename = "ExifToolVersionNumber")]
@@ -53,22 +294,24 @@ pub struct CSQExifData {
     pub planck_o: f32,
     #[serde(rename = "CameraPartNumber")]
     pub camera_part_number: Option<String>,
+    /// In meters.
     #[serde(rename = "FocusDistance")]
-    pub focus_distance: Option<String>,
+    pub focus_distance: Option<f32>,
     #[serde(rename = "BelowColor")]
     pub below_color: Option<String>,
     #[serde(rename = "GPSDilutionOfPrecision")]
-    pub gps_dilution_of_precision: Option<String>,
+    pub gps_dilution_of_precision: Option<f32>,
     #[serde(rename = "FileAccessDate/Time")]
-    pub file_access_date_time: Option<String>,
+    pub file_access_date_time: Option<DateTime<FixedOffset>>,
     #[serde(rename = "PlanckB")]
     pub planck_b: f32,
+    /// In degrees Celsius.
     #[serde(rename = "CameraTemperatureMaxSaturated")]
-    pub camera_temperature_max_saturated: Option<String>,
+    pub camera_temperature_max_saturated: Option<f32>,
     #[serde(rename = "GPSPosition")]
     pub gps_position: Option<String>,
     #[serde(rename = "PaletteMethod")]
-    pub palette_method: Option<String>,
+    pub palette_method: Option<u32>,
     #[serde(rename = "Palette")]
     pub palette: Option<String>,
     #[serde(rename = "Isotherm2Color")]
@@ -77,38 +320,45 @@ pub struct CSQExifData {
     pub ir_window_transmission: f32,
     #[serde(rename = "AtmosphericTransAlpha1")]
     pub atmospheric_trans_alpha1: f32,
+    /// In signed decimal degrees, negative west of Greenwich.
     #[serde(rename = "GPSLongitude")]
-    pub gps_longitude: Option<String>,
+    pub gps_longitude: Option<f64>,
     #[serde(rename = "MIMEType")]
     pub mime_type: Option<String>,
+    /// Whether the camera had a GPS fix.
     #[serde(rename = "GPSValid")]
-    pub gps_valid: Option<String>,
+    pub gps_valid: Option<bool>,
+    /// In meters.
     #[serde(rename = "GPSAltitude")]
-    pub gps_altitude: Option<String>,
+    pub gps_altitude: Option<f64>,
+    /// The upper limit of the measurement range in degrees Celsius.
     #[serde(rename = "CameraTemperatureRangeMax")]
-    pub camera_temperature_range_max: Option<String>,
+    pub camera_temperature_range_max: Option<f32>,
     #[serde(rename = "FileType")]
     pub file_type: Option<String>,
     #[serde(rename = "RelativeHumidity")]
     pub relative_humidity: f32,
+    /// In degrees Celsius.
     #[serde(rename = "CameraTemperatureMinWarn")]
-    pub camera_temperature_min_warn: Option<String>,
+    pub camera_temperature_min_warn: Option<f32>,
     #[serde(rename = "CameraSerialNumber")]
     pub camera_serial_number: Option<String>,
+    /// In degrees Celsius.
     #[serde(rename = "CameraTemperatureMaxClip")]
-    pub camera_temperature_max_clip: Option<String>,
+    pub camera_temperature_max_clip: Option<f32>,
     #[serde(rename = "UnderflowColor")]
     pub underflow_color: Option<String>,
     #[serde(rename = "RawThermalImage")]
     pub raw_thermal_image: Option<String>,
+    /// In signed decimal degrees, negative south of the equator.
     #[serde(rename = "GPSLatitude")]
-    pub gps_latitude: Option<String>,
+    pub gps_latitude: Option<f64>,
     #[serde(rename = "PlanckF")]
     pub planck_f: f32,
     #[serde(rename = "GPSMapDatum")]
     pub gps_map_datum: Option<String>,
     #[serde(rename = "RawValueMedian")]
-    pub raw_value_median: Option<String>,
+    pub raw_value_median: Option<u16>,
     #[serde(rename = "FileTypeExtension")]
     pub file_type_extension: Option<String>,
     #[serde(rename = "CameraModel")]
@@ -122,163 +372,537 @@ pub struct CSQExifData {
     #[serde(rename = "AtmosphericTransX")]
     pub atmospheric_trans_x: f32,
     #[serde(rename = "RawValueRange")]
-    pub raw_value_range: Option<String>,
+    pub raw_value_range: Option<u16>,
+    /// The capture time, with millisecond precision and in the time zone of the camera.
     #[serde(rename = "Date/TimeOriginal")]
-    pub date_time_original: Option<String>,
+    pub date_time_original: Option<DateTime<FixedOffset>>,
     #[serde(rename = "PaletteStretch")]
-    pub palette_stretch: Option<String>,
+    pub palette_stretch: Option<u32>,
     #[serde(rename = "PlanckR2")]
     pub planck_r2: f32,
+    /// In pixels.
     #[serde(rename = "RawThermalImageWidth")]
-    pub raw_thermal_image_width: Option<String>,
+    pub raw_thermal_image_width: Option<u32>,
     #[serde(rename = "PaletteFileName")]
     pub palette_file_name: Option<String>,
     #[serde(rename = "PlanckR1")]
     pub planck_r1: f32,
     #[serde(rename = "FileModificationDate/Time")]
-    pub file_modification_date_time: Option<String>,
+    pub file_modification_date_time: Option<DateTime<FixedOffset>>,
     #[serde(rename = "LensModel")]
     pub lens_model: Option<String>,
     #[serde(rename = "LensSerialNumber")]
     pub lens_serial_number: Option<String>,
+    /// In pixels.
     #[serde(rename = "RawThermalImageHeight")]
-    pub raw_thermal_image_height: Option<String>,
+    pub raw_thermal_image_height: Option<u32>,
+    /// In micrometers.
     #[serde(rename = "PeakSpectralSensitivity")]
-    pub peak_spectral_sensitivity: Option<String>,
+    pub peak_spectral_sensitivity: Option<f32>,
     #[serde(rename = "ObjectDistance")]
     pub object_distance: f32,
     #[serde(rename = "AtmosphericTransBeta1")]
     pub atmospheric_trans_beta1: f32,
     #[serde(rename = "IRWindowTemperature")]
     pub ir_window_temperature: f32,
+    /// In degrees.
     #[serde(rename = "FieldOfView")]
-    pub field_of_view: Option<String>,
+    pub field_of_view: Option<f32>,
     #[serde(rename = "RawValueRangeMax")]
-    pub raw_value_range_max: Option<String>,
+    pub raw_value_range_max: Option<u16>,
+    /// In frames per second.
     #[serde(rename = "FrameRate")]
-    pub frame_rate: Option<String>,
+    pub frame_rate: Option<f32>,
     #[serde(rename = "PaletteColors")]
-    pub palette_colors: Option<String>,
+    pub palette_colors: Option<u32>,
+    /// In degrees clockwise from north.
     #[serde(rename = "GpsImgDirection")]
-    pub gps_img_direction: Option<String>,
+    pub gps_img_direction: Option<f32>,
     #[serde(rename = "Emissivity")]
     pub emissivity: f32,
     #[serde(rename = "AtmosphericTransAlpha2")]
     pub atmospheric_trans_alpha2: f32,
     #[serde(rename = "AtmosphericTransBeta2")]
     pub atmospheric_trans_beta2: f32,
+    /// In degrees Celsius.
     #[serde(rename = "CameraTemperatureMinSaturated")]
-    pub camera_temperature_min_saturated: Option<String>,
+    pub camera_temperature_min_saturated: Option<f32>,
     #[serde(rename = "AboveColor")]
     pub above_color: Option<String>,
+    /// In pixels.
+    #[serde(rename = "EmbeddedImageWidth")]
+    pub embedded_image_width: Option<u32>,
+    /// In pixels.
+    #[serde(rename = "EmbeddedImageHeight")]
+    pub embedded_image_height: Option<u32>,
+    #[serde(rename = "EmbeddedImageType")]
+    pub embedded_image_type: Option<String>,
+    #[serde(rename = "Real2IR")]
+    pub real2ir: Option<f32>,
+    #[serde(rename = "OffsetX")]
+    pub offset_x: Option<i16>,
+    #[serde(rename = "OffsetY")]
+    pub offset_y: Option<i16>,
+    #[serde(rename = "PiPX1")]
+    pub pip_x1: Option<i16>,
+    #[serde(rename = "PiPX2")]
+    pub pip_x2: Option<i16>,
+    #[serde(rename = "PiPY1")]
+    pub pip_y1: Option<i16>,
+    #[serde(rename = "PiPY2")]
+    pub pip_y2: Option<i16>,
+    /// The calibration and object parameters that were not taken from the file.
+    #[serde(rename = "AssumedValues")]
+    pub assumed_values: Vec<AssumedValue>,
+    /// Every tag the metadata was built from, with exiftool names and formatting, including
+    /// the tags that have no field, e.g. `Meas1Label`.
+    #[serde(rename = "Tags")]
+    pub tags: BTreeMap<String, String>,
 }
 
-impl<'de> Deserialize<'de> for CSQExifData {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let map: HashMap<String, String> = HashMap::deserialize(deserializer)?;
+impl CSQExifData {
+    /// The raw value of `tag`, with its exiftool name and formatting, e.g. `get("FieldOfView")`
+    /// returns `Some("24.6 deg")`. Tags without a field of their own are kept as well.
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        self.tags.get(tag).map(String::as_str)
+    }
+
+    /// The capture time of the frame, with millisecond precision and in the time zone of the
+    /// camera.
+    pub fn capture_time(&self) -> Option<DateTime<FixedOffset>> {
+        self.date_time_original
+    }
+
+    /// The frame rate the camera was set to, in frames per second.
+    pub fn nominal_frame_rate(&self) -> Option<f64> {
+        let rate = f64::from(self.frame_rate?);
+        (rate > 0.0).then_some(rate)
+    }
+
+    /// The measurement range of the camera in degrees Celsius, from
+    /// `camera_temperature_range_min` and `camera_temperature_range_max`. Returns `None` if the
+    /// camera did not store a range.
+    pub fn camera_temperature_range(&self) -> Option<(f32, f32)> {
+        let min = self.camera_temperature_range_min?;
+        let max = self.camera_temperature_range_max?;
+        (max > min).then_some((min, max))
+    }
+
+    /// Builds the metadata from exiftool style tags, as collected from the FFF records.
+    ///
+    /// Numbers are parsed from exiftool's display formatting, with temperatures converted to
+    /// degrees Celsius. Optional values that cannot be parsed are left out, and missing
+    /// calibration and object parameters are replaced by the FLIR defaults, see
+    /// `MetadataOverrides`.
+    pub fn from_tags(map: &HashMap<String, String>) -> Result<Self> {
+        Self::from_tags_with_overrides(map, &MetadataOverrides::default())
+    }
+
+    /// Like `from_tags`, with `overrides` for the parameters the file lacks.
+    pub fn from_tags_with_overrides(
+        map: &HashMap<String, String>,
+        overrides: &MetadataOverrides,
+    ) -> Result<Self> {
+        fn get_number<T: FromStr>(map: &HashMap<String, String>, key: &str) -> Option<T> {
+            map.get(key).and_then(|v| parse_number(v))
+        }
 
         let get_optional_string = |key: &str| -> Option<String> { map.get(key).cloned() };
 
-        let get_float = |key: &'static str| -> Result<f32, D::Error> {
-            map.get(key)
-                .ok_or_else(|| serde::de::Error::missing_field(key))?
-                .split_whitespace()
-                .collect::<Vec<&str>>()
-                .first()
-                .ok_or_else(|| {
-                    serde::de::Error::custom(format!("Failed to split whitespace for {}", key))
-                })?
-                .parse::<f32>()
-                .map_err(|_| serde::de::Error::custom(format!("Failed to parse float for {}", key)))
+        let mut assumed_values = vec![];
+        let mut resolve = |key: &str,
+                           parse: fn(&str) -> Option<f32>,
+                           override_value: Option<f32>,
+                           default: Option<f32>|
+         -> Result<f32> {
+            let value = map.get(key);
+            if let Some(parsed) = value.and_then(|v| parse(v)) {
+                return Ok(parsed);
+            }
+
+            let (assumed, source) = match (override_value, default) {
+                (Some(v), _) => (v, ValueSource::Override),
+                (None, Some(v)) => (v, ValueSource::Default),
+                (None, None) => {
+                    return Err(match value {
+                        Some(value) => Error::InvalidMetadata {
+                            field: key.to_string(),
+                            value: value.clone(),
+                        },
+                        None => Error::MetadataMissing(key.to_string()),
+                    })
+                }
+            };
+            assumed_values.push(AssumedValue {
+                field: key.to_string(),
+                value: assumed,
+                source,
+            });
+            Ok(assumed)
+        };
+        let o = overrides;
+
+        let emissivity = resolve(
+            "Emissivity",
+            parse_number,
+            o.emissivity,
+            Some(DEFAULT_EMISSIVITY),
+        )?;
+        let object_distance = resolve(
+            "ObjectDistance",
+            parse_number,
+            o.object_distance,
+            Some(DEFAULT_OBJECT_DISTANCE),
+        )?;
+        let reflected_apparent_temperature = resolve(
+            "ReflectedApparentTemperature",
+            parse_temperature,
+            o.reflected_apparent_temperature,
+            Some(DEFAULT_TEMPERATURE),
+        )?;
+        let atmospheric_temperature = resolve(
+            "AtmosphericTemperature",
+            parse_temperature,
+            o.atmospheric_temperature,
+            Some(DEFAULT_TEMPERATURE),
+        )?;
+        let ir_window_temperature = resolve(
+            "IRWindowTemperature",
+            parse_temperature,
+            o.ir_window_temperature,
+            Some(atmospheric_temperature),
+        )?;
+        let ir_window_transmission = resolve(
+            "IRWindowTransmission",
+            parse_number,
+            o.ir_window_transmission,
+            Some(DEFAULT_IR_WINDOW_TRANSMISSION),
+        )?;
+        let relative_humidity = resolve(
+            "RelativeHumidity",
+            parse_number,
+            o.relative_humidity,
+            Some(DEFAULT_RELATIVE_HUMIDITY),
+        )?;
+        let planck_r1 = resolve("PlanckR1", parse_number, o.planck_r1, None)?;
+        let planck_r2 = resolve("PlanckR2", parse_number, o.planck_r2, None)?;
+        let planck_b = resolve("PlanckB", parse_number, o.planck_b, None)?;
+        let planck_f = resolve("PlanckF", parse_number, o.planck_f, None)?;
+        let planck_o = resolve("PlanckO", parse_number, o.planck_o, None)?;
+        let atmospheric_trans_alpha1 = resolve(
+            "AtmosphericTransAlpha1",
+            parse_number,
+            o.atmospheric_trans_alpha1,
+            Some(DEFAULT_ATMOSPHERIC_TRANS_ALPHA1),
+        )?;
+        let atmospheric_trans_alpha2 = resolve(
+            "AtmosphericTransAlpha2",
+            parse_number,
+            o.atmospheric_trans_alpha2,
+            Some(DEFAULT_ATMOSPHERIC_TRANS_ALPHA2),
+        )?;
+        let atmospheric_trans_beta1 = resolve(
+            "AtmosphericTransBeta1",
+            parse_number,
+            o.atmospheric_trans_beta1,
+            Some(DEFAULT_ATMOSPHERIC_TRANS_BETA1),
+        )?;
+        let atmospheric_trans_beta2 = resolve(
+            "AtmosphericTransBeta2",
+            parse_number,
+            o.atmospheric_trans_beta2,
+            Some(DEFAULT_ATMOSPHERIC_TRANS_BETA2),
+        )?;
+        let atmospheric_trans_x = resolve(
+            "AtmosphericTransX",
+            parse_number,
+            o.atmospheric_trans_x,
+            Some(DEFAULT_ATMOSPHERIC_TRANS_X),
+        )?;
+
+        let get_optional_temperature =
+            |key: &str| -> Option<f32> { map.get(key).and_then(|v| parse_temperature(v)) };
+        let get_date_time = |key: &str| -> Option<DateTime<FixedOffset>> {
+            map.get(key).and_then(|v| parse_date_time(v))
+        };
+        let get_degrees = |key: &str, reference: &str, negative: char| -> Option<f64> {
+            parse_degrees(
+                map.get(key)?,
+                map.get(reference).map(String::as_str),
+                negative,
+            )
         };
 
         Ok(CSQExifData {
             overflow_color: get_optional_string("OverflowColor"),
             gps_longitude_ref: get_optional_string("GPSLongitudeRef"),
             gps_img_direction_ref: get_optional_string("GPSImgDirectionRef"),
-            file_inode_change_date_time: get_optional_string("FileInodeChangeDate/Time"),
+            file_inode_change_date_time: get_date_time("FileInodeChangeDate/Time"),
             file_permissions: get_optional_string("FilePermissions"),
-            atmospheric_temperature: get_float("AtmosphericTemperature")?,
+            atmospheric_temperature,
             lens_part_number: get_optional_string("LensPartNumber"),
-            camera_temperature_range_min: get_optional_string("CameraTemperatureRangeMin"),
+            camera_temperature_range_min: get_optional_temperature("CameraTemperatureRangeMin"),
             camera_software: get_optional_string("CameraSoftware"),
             file_name: get_optional_string("FileName"),
-            focus_step_count: get_optional_string("FocusStepCount"),
-            raw_value_range_min: get_optional_string("RawValueRangeMin"),
+            focus_step_count: get_number(map, "FocusStepCount"),
+            raw_value_range_min: get_number(map, "RawValueRangeMin"),
             gps_latitude_ref: get_optional_string("GPSLatitudeRef"),
             creator_software: get_optional_string("CreatorSoftware"),
-            camera_temperature_max_warn: get_optional_string("CameraTemperatureMaxWarn"),
+            camera_temperature_max_warn: get_optional_temperature("CameraTemperatureMaxWarn"),
             raw_thermal_image_type: get_optional_string("RawThermalImageType"),
             directory: get_optional_string("Directory"),
             file_size: get_optional_string("FileSize"),
-            camera_temperature_min_clip: get_optional_string("CameraTemperatureMinClip"),
+            camera_temperature_min_clip: get_optional_temperature("CameraTemperatureMinClip"),
             filter_serial_number: get_optional_string("FilterSerialNumber"),
             exif_tool_version_number: get_optional_string("ExifToolVersionNumber"),
             filter_model: get_optional_string("FilterModel"),
-            planck_o: get_float("PlanckO")?,
+            planck_o,
             camera_part_number: get_optional_string("CameraPartNumber"),
-            focus_distance: get_optional_string("FocusDistance"),
+            focus_distance: get_number(map, "FocusDistance"),
             below_color: get_optional_string("BelowColor"),
-            gps_dilution_of_precision: get_optional_string("GPSDilutionOfPrecision"),
-            file_access_date_time: get_optional_string("FileAccessDate/Time"),
-            planck_b: get_float("PlanckB")?,
-            camera_temperature_max_saturated: get_optional_string("CameraTemperatureMaxSaturated"),
+            gps_dilution_of_precision: get_number(map, "GPSDilutionOfPrecision"),
+            file_access_date_time: get_date_time("FileAccessDate/Time"),
+            planck_b,
+            camera_temperature_max_saturated: get_optional_temperature(
+                "CameraTemperatureMaxSaturated",
+            ),
             gps_position: get_optional_string("GPSPosition"),
-            palette_method: get_optional_string("PaletteMethod"),
+            palette_method: get_number(map, "PaletteMethod"),
             palette: get_optional_string("Palette"),
             isotherm2_color: get_optional_string("Isotherm2Color"),
-            ir_window_transmission: get_float("IRWindowTransmission")?,
-            atmospheric_trans_alpha1: get_float("AtmosphericTransAlpha1")?,
-            gps_longitude: get_optional_string("GPSLongitude"),
+            ir_window_transmission,
+            atmospheric_trans_alpha1,
+            gps_longitude: get_degrees("GPSLongitude", "GPSLongitudeRef", 'W'),
             mime_type: get_optional_string("MIMEType"),
-            gps_valid: get_optional_string("GPSValid"),
-            gps_altitude: get_optional_string("GPSAltitude"),
-            camera_temperature_range_max: get_optional_string("CameraTemperatureRangeMax"),
+            gps_valid: get_optional_string("GPSValid")
+                .map(|v| matches!(v.as_str(), "Valid" | "Yes" | "1")),
+            gps_altitude: get_number(map, "GPSAltitude"),
+            camera_temperature_range_max: get_optional_temperature("CameraTemperatureRangeMax"),
             file_type: get_optional_string("FileType"),
-            relative_humidity: get_float("RelativeHumidity")?,
-            camera_temperature_min_warn: get_optional_string("CameraTemperatureMinWarn"),
+            relative_humidity,
+            camera_temperature_min_warn: get_optional_temperature("CameraTemperatureMinWarn"),
             camera_serial_number: get_optional_string("CameraSerialNumber"),
-            camera_temperature_max_clip: get_optional_string("CameraTemperatureMaxClip"),
+            camera_temperature_max_clip: get_optional_temperature("CameraTemperatureMaxClip"),
             underflow_color: get_optional_string("UnderflowColor"),
             raw_thermal_image: get_optional_string("RawThermalImage"),
-            gps_latitude: get_optional_string("GPSLatitude"),
-            planck_f: get_float("PlanckF")?,
+            gps_latitude: get_degrees("GPSLatitude", "GPSLatitudeRef", 'S'),
+            planck_f,
             gps_map_datum: get_optional_string("GPSMapDatum"),
-            raw_value_median: get_optional_string("RawValueMedian"),
+            raw_value_median: get_number(map, "RawValueMedian"),
             file_type_extension: get_optional_string("FileTypeExtension"),
             camera_model: get_optional_string("CameraModel"),
             palette_name: get_optional_string("PaletteName"),
-            reflected_apparent_temperature: get_float("ReflectedApparentTemperature")?,
+            reflected_apparent_temperature,
             isotherm1_color: get_optional_string("Isotherm1Color"),
-            atmospheric_trans_x: get_float("AtmosphericTransX")?,
-            raw_value_range: get_optional_string("RawValueRange"),
-            date_time_original: get_optional_string("Date/TimeOriginal"),
-            palette_stretch: get_optional_string("PaletteStretch"),
-            planck_r2: get_float("PlanckR2")?,
-            raw_thermal_image_width: get_optional_string("RawThermalImageWidth"),
+            atmospheric_trans_x,
+            raw_value_range: get_number(map, "RawValueRange"),
+            date_time_original: get_date_time("Date/TimeOriginal"),
+            palette_stretch: get_number(map, "PaletteStretch"),
+            planck_r2,
+            raw_thermal_image_width: get_number(map, "RawThermalImageWidth"),
             palette_file_name: get_optional_string("PaletteFileName"),
-            planck_r1: get_float("PlanckR1")?,
-            file_modification_date_time: get_optional_string("FileModificationDate/Time"),
+            planck_r1,
+            file_modification_date_time: get_date_time("FileModificationDate/Time"),
             lens_model: get_optional_string("LensModel"),
             lens_serial_number: get_optional_string("LensSerialNumber"),
-            raw_thermal_image_height: get_optional_string("RawThermalImageHeight"),
-            peak_spectral_sensitivity: get_optional_string("PeakSpectralSensitivity"),
-            object_distance: get_float("ObjectDistance")?,
-            atmospheric_trans_beta1: get_float("AtmosphericTransBeta1")?,
-            ir_window_temperature: get_float("IRWindowTemperature")?,
-            field_of_view: get_optional_string("FieldOfView"),
-            raw_value_range_max: get_optional_string("RawValueRangeMax"),
-            frame_rate: get_optional_string("FrameRate"),
-            palette_colors: get_optional_string("PaletteColors"),
-            gps_img_direction: get_optional_string("GpsImgDirection"),
-            emissivity: get_float("Emissivity")?,
-            atmospheric_trans_alpha2: get_float("AtmosphericTransAlpha2")?,
-            atmospheric_trans_beta2: get_float("AtmosphericTransBeta2")?,
-            camera_temperature_min_saturated: get_optional_string("CameraTemperatureMinSaturated"),
+            raw_thermal_image_height: get_number(map, "RawThermalImageHeight"),
+            peak_spectral_sensitivity: get_number(map, "PeakSpectralSensitivity"),
+            object_distance,
+            atmospheric_trans_beta1,
+            ir_window_temperature,
+            field_of_view: get_number(map, "FieldOfView"),
+            raw_value_range_max: get_number(map, "RawValueRangeMax"),
+            frame_rate: get_number(map, "FrameRate"),
+            palette_colors: get_number(map, "PaletteColors"),
+            gps_img_direction: get_number(map, "GPSImgDirection")
+                .or_else(|| get_number(map, "GpsImgDirection")),
+            emissivity,
+            atmospheric_trans_alpha2,
+            atmospheric_trans_beta2,
+            camera_temperature_min_saturated: get_optional_temperature(
+                "CameraTemperatureMinSaturated",
+            ),
             above_color: get_optional_string("AboveColor"),
+            embedded_image_width: get_number(map, "EmbeddedImageWidth"),
+            embedded_image_height: get_number(map, "EmbeddedImageHeight"),
+            embedded_image_type: get_optional_string("EmbeddedImageType"),
+            real2ir: get_number(map, "Real2IR"),
+            offset_x: get_number(map, "OffsetX"),
+            offset_y: get_number(map, "OffsetY"),
+            pip_x1: get_number(map, "PiPX1"),
+            pip_x2: get_number(map, "PiPX2"),
+            pip_y1: get_number(map, "PiPY1"),
+            pip_y2: get_number(map, "PiPY2"),
+            assumed_values,
+            tags: map.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
         })
     }
 }
+
+/// Splits an exiftool value like `1.5 m` into the number and its unit. Some firmware writes
+/// the unit without a space, like `20.0C`.
+fn split_unit(value: &str) -> (&str, &str) {
+    let value = value.trim();
+    match value.split_once(char::is_whitespace) {
+        Some((number, unit)) if !number.is_empty() => (number, unit.trim()),
+        _ => {
+            let end = value
+                .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+')))
+                .unwrap_or(value.len());
+            (&value[..end], value[end..].trim())
+        }
+    }
+}
+
+/// Parses the number at the start of an exiftool value like `1.5 m`.
+fn parse_number<T: FromStr>(value: &str) -> Option<T> {
+    let value = value.trim();
+    value
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+        .or_else(|| split_unit(value).0.parse().ok())
+}
+
+/// Parses an exiftool temperature like `20.0 C` to degrees Celsius. Values without a unit are
+/// taken to be in degrees Celsius.
+fn parse_temperature(value: &str) -> Option<f32> {
+    let (number, unit) = split_unit(value);
+    let number: f32 = number.parse().ok()?;
+    match unit.trim_start_matches('°') {
+        "" | "C" => Some(number),
+        "K" => Some(number - 273.15),
+        "F" => Some((number - 32.0) * 5.0 / 9.0),
+        _ => None,
+    }
+}
+
+/// Parses an exiftool date like `2024:05:01 12:30:00.250+02:00`, with or without the
+/// milliseconds.
+fn parse_date_time(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(value, DATE_TIME_FORMAT)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y:%m:%d %H:%M:%S%:z"))
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+}
+
+/// Reads the metadata from a JSON object of exiftool tags, as written by `exiftool -j`, or from
+/// the JSON `CSQExifData` serializes to, whose `Tags` hold every tag it was built from. The
+/// `AssumedValues` of the latter are restored as well, as they are not part of the tags.
+impl<'de> Deserialize<'de> for CSQExifData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut map: HashMap<String, serde_json::Value> = HashMap::deserialize(deserializer)?;
+
+        let assumed_values: Option<Vec<AssumedValue>> = map
+            .remove("AssumedValues")
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(serde::de::Error::custom)?;
+
+        let map = match map.remove("Tags") {
+            Some(serde_json::Value::Object(tags)) => tags.into_iter().collect(),
+            _ => map,
+        };
+        let tags = map
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => (key, value),
+                other => (key, other.to_string()),
+            })
+            .collect();
+
+        let Some(assumed_values) = assumed_values else {
+            return CSQExifData::from_tags(&tags).map_err(serde::de::Error::custom);
+        };
+
+        let mut overrides = MetadataOverrides::default();
+        for assumed in &assumed_values {
+            if let Some(value) = overrides.field_mut(&assumed.field) {
+                *value = Some(assumed.value);
+            }
+        }
+        let mut metadata = CSQExifData::from_tags_with_overrides(&tags, &overrides)
+            .map_err(serde::de::Error::custom)?;
+        metadata.assumed_values = assumed_values;
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> HashMap<String, String> {
+        [
+            ("ReflectedApparentTemperature", "20.0 C"),
+            ("AtmosphericTemperature", "20.0 C"),
+            ("IRWindowTemperature", "20.0 C"),
+            ("IRWindowTransmission", "1.00"),
+            ("RelativeHumidity", "50.0 %"),
+            ("PlanckR2", "0.046"),
+            ("PlanckB", "1428"),
+            ("PlanckF", "1"),
+            ("PlanckO", "-342"),
+            ("AtmosphericTransAlpha1", "0.006569"),
+            ("AtmosphericTransAlpha2", "0.012620"),
+            ("AtmosphericTransBeta1", "-0.002276"),
+            ("AtmosphericTransBeta2", "-0.006670"),
+            ("AtmosphericTransX", "1.900000"),
+            ("FieldOfView", "24.6 deg"),
+            ("Meas1Label", "Sp1"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn serialized_metadata_keeps_assumed_values() {
+        let overrides = MetadataOverrides {
+            emissivity: Some(0.5),
+            planck_r1: Some(16556.0),
+            ..Default::default()
+        };
+        let metadata = CSQExifData::from_tags_with_overrides(&tags(), &overrides).unwrap();
+
+        let json = serde_json::to_string(&metadata).unwrap();
+        let read: CSQExifData = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(read.emissivity, 0.5);
+        assert_eq!(read.planck_r1, 16556.0);
+        assert_eq!(read.object_distance, DEFAULT_OBJECT_DISTANCE);
+        assert_eq!(read.assumed_values, metadata.assumed_values);
+        assert_eq!(
+            read.assumed_values
+                .iter()
+                .map(|a| (a.field.as_str(), a.source))
+                .collect::<Vec<_>>(),
+            [
+                ("Emissivity", ValueSource::Override),
+                ("ObjectDistance", ValueSource::Default),
+                ("PlanckR1", ValueSource::Override),
+            ]
+        );
+        assert_eq!(read.get("Meas1Label"), Some("Sp1"));
+        assert_eq!(read.field_of_view, Some(24.6));
+    }
+
+    #[test]
+    fn exiftool_json_is_read_from_tags() {
+        let mut json: serde_json::Map<String, serde_json::Value> =
+            tags().into_iter().map(|(k, v)| (k, v.into())).collect();
+        json.insert("PlanckR1".into(), 16556.0.into());
+        json.insert("Emissivity".into(), 0.9.into());
+
+        let read: CSQExifData = serde_json::from_value(json.into()).unwrap();
+
+        assert_eq!(read.planck_r1, 16556.0);
+        assert_eq!(read.emissivity, 0.9);
+        assert_eq!(read.assumed_values.len(), 1);
+        assert_eq!(read.assumed_values[0].field, "ObjectDistance");
+    }
+}
@@ -1,54 +1,13 @@
-use std::env;
-
-use anyhow::{anyhow, Result};
-use ndarray::{Array2, ShapeBuilder};
-use pyo3::prelude::*;
+use ndarray::Array2;
 
+use crate::error::Result;
 use crate::types::CSQExifData;
 
-// The images in the CSQ file are old style JPEGs.
-// https://github.com/haraldk/TwelveMonkeys/issues/67
-pub fn decode_jpeg_py(img: &[u8]) -> Result<Array2<f32>> {
-    let decoded = Python::with_gil(|py| -> PyResult<Vec<Vec<f32>>> {
-        #[cfg(target_os = "macos")]
-        if let Ok(venv) = env::var("VIRTUAL_ENV") {
-            let sys = py.import_bound("sys")?;
-            let syspath = sys.getattr("path")?;
-            let version_info = py.version_info();
-
-            syspath.call_method1(
-                "append",
-                (format!(
-                    "{}/lib/python{}.{}/site-packages",
-                    venv, version_info.major, version_info.minor
-                ),),
-            )?;
-        }
-
-        let libjpeg = PyModule::import_bound(py, "pylibjpeg")
-            .expect("Failed to import libjpeg python module");
-
-        let res = libjpeg.getattr("decode")?.call1((img,))?;
-
-        let v = res.extract::<Vec<Vec<f32>>>()?;
-
-        Ok(v)
-    })?;
+/// Converts raw sensor values to temperatures in degrees Celsius, using the Planck constants and
+/// the atmospheric and object parameters of `metadata`.
+pub fn raw_to_temp(raw: &Array2<u16>, metadata: &CSQExifData) -> Result<Array2<f32>> {
+    let raw = raw.mapv(f32::from);
 
-    let cols = decoded.len();
-    let first_col_length = &decoded[0].len();
-    let rows = if cols > 0 { *first_col_length } else { 0 };
-
-    let flat: Vec<f32> = decoded.into_iter().flatten().collect();
-
-    let arr = Array2::from_shape_vec((rows, cols).f(), flat.to_vec())
-        .map_err(|e| anyhow!("Failed to create ndarray: {e}"))?
-        .reversed_axes();
-
-    Ok(arr)
-}
-
-pub fn raw_to_temp(raw: &Array2<f32>, metadata: CSQExifData) -> Result<Box<Array2<f32>>> {
     let e = metadata.emissivity;
     let od = metadata.object_distance;
     let r_temp = metadata.reflected_apparent_temperature;
@@ -97,7 +56,7 @@ pub fn raw_to_temp(raw: &Array2<f32>, metadata: CSQExifData) -> Result<Box<Array
     let raw_atm2 = pr1 / (pr2 * ((pb / (a_temp + 273.15)).exp() - pf)) - po;
     let raw_atm2_attn = (1.0 - tau2) / e / tau1 / irt / tau2 * raw_atm2;
 
-    let raw_obj = raw / e / tau1 / irt / tau2
+    let raw_obj = &raw / e / tau1 / irt / tau2
         - raw_atm1_attn
         - raw_atm2_attn
         - raw_wind_attn
@@ -106,7 +65,5 @@ pub fn raw_to_temp(raw: &Array2<f32>, metadata: CSQExifData) -> Result<Box<Array
 
     let temp_c = pb / (pr1 / (pr2 * (&raw_obj + po)) + pf).mapv(|x| x.ln()) - 273.15;
 
-    let temp_box = Box::new(temp_c);
-
-    Ok(temp_box)
+    Ok(temp_c)
 }
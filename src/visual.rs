@@ -0,0 +1,149 @@
+use ndarray::Array3;
+
+use crate::error::{Error, Result};
+
+/// The photo of the visual camera, which FLIR cameras with a visible light sensor embed next to
+/// the thermal image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisualImage {
+    /// The width in pixels, as stored in the EmbeddedImage record.
+    pub width: usize,
+    /// The height in pixels, as stored in the EmbeddedImage record.
+    pub height: usize,
+    /// The format of `data`, `JPG` or `PNG`, named like exiftool's `EmbeddedImageType`.
+    pub image_type: String,
+    /// The encoded image, e.g. to be written to a .jpg file as is.
+    pub data: Vec<u8>,
+}
+
+impl VisualImage {
+    /// Decodes the image into an array of shape (height, width, 3) of RGB values.
+    pub fn decode_rgb(&self) -> Result<Array3<u8>> {
+        let (width, height, rgb) = match self.image_type.as_str() {
+            "JPG" => decode_jpeg(&self.data)?,
+            "PNG" => decode_png(&self.data)?,
+            other => return Err(Error::UnsupportedImageType(other.to_string())),
+        };
+
+        Array3::from_shape_vec((height, width, 3), rgb)
+            .map_err(|e| Error::DecodeFailed(e.to_string()))
+    }
+}
+
+fn decode_jpeg(data: &[u8]) -> Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder
+        .decode()
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| Error::DecodeFailed("JPEG has no image".into()))?;
+
+    let rgb = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => pixels,
+        jpeg_decoder::PixelFormat::L8 => pixels.iter().flat_map(|&l| [l, l, l]).collect(),
+        jpeg_decoder::PixelFormat::L16 => pixels
+            .chunks_exact(2)
+            .flat_map(|l| [l[0], l[0], l[0]])
+            .collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => pixels
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let k = 255 - p[3] as u16;
+                [p[0], p[1], p[2]].map(|c| ((255 - c as u16) * k / 255) as u8)
+            })
+            .collect(),
+    };
+
+    Ok((info.width as usize, info.height as usize, rgb))
+}
+
+fn decode_png(data: &[u8]) -> Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| Error::DecodeFailed(e.to_string()))?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgb = match info.color_type {
+        png::ColorType::Rgb => pixels.to_vec(),
+        png::ColorType::Rgba => pixels
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&l| [l, l, l]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0]])
+            .collect(),
+        png::ColorType::Indexed => {
+            return Err(Error::DecodeFailed(
+                "PNG palette was not expanded to RGB".into(),
+            ))
+        }
+    };
+
+    Ok((info.width as usize, info.height as usize, rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr3;
+
+    fn png(color: png::ColorType, width: u32, height: u32, pixels: &[u8]) -> VisualImage {
+        let mut data = vec![];
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+
+        VisualImage {
+            width: width as usize,
+            height: height as usize,
+            image_type: "PNG".into(),
+            data,
+        }
+    }
+
+    #[test]
+    fn png_is_decoded_to_rgb() {
+        let rgba = png(png::ColorType::Rgba, 2, 1, &[1, 2, 3, 255, 4, 5, 6, 0]);
+        assert_eq!(rgba.decode_rgb().unwrap(), arr3(&[[[1, 2, 3], [4, 5, 6]]]));
+
+        let gray = png(png::ColorType::Grayscale, 1, 2, &[7, 8]);
+        assert_eq!(
+            gray.decode_rgb().unwrap(),
+            arr3(&[[[7, 7, 7]], [[8, 8, 8]]])
+        );
+    }
+
+    #[test]
+    fn unknown_and_damaged_images_are_rejected() {
+        let image = VisualImage {
+            width: 1,
+            height: 1,
+            image_type: "BMP".into(),
+            data: vec![],
+        };
+        assert!(matches!(
+            image.decode_rgb(),
+            Err(Error::UnsupportedImageType(t)) if t == "BMP"
+        ));
+
+        let jpeg = VisualImage {
+            image_type: "JPG".into(),
+            data: vec![0xff, 0xd8, 0xff, 0xe0],
+            ..image
+        };
+        assert!(matches!(jpeg.decode_rgb(), Err(Error::DecodeFailed(_))));
+    }
+}